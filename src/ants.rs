@@ -1,13 +1,327 @@
-use crate::{state::State, structs::Move};
+use serde::Deserialize;
 
-#[allow(dead_code)]
-pub struct AntColonyOptimizationAlgorithm {
+use crate::{state::State, structs::{Move, PlanetId}, strategy::Strategy, deadline::Deadline, sim::Rng};
+
+// bounds on the pheromone of an edge, so that no edge is ever ruled out or always taken
+const MIN_PHEROMONE: f32 = 0.01;
+const MAX_PHEROMONE: f32 = 10.0;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AntsConfig {
+    // ants that build a plan every iteration
+    pub ant_count: usize,
+    // upper bound of iterations per turn, the deadline usually ends the search earlier
+    pub max_iterations: usize,
+    // weight of the pheromone when an ant chooses a destination
+    pub alpha: f32,
+    // weight of the profitability when an ant chooses a destination
+    pub beta: f32,
+    // fraction of the pheromone that evaporates every turn
+    pub evaporation: f32,
+    // pheromone the best ant of an iteration lays on the edges of its plan
+    pub deposit: f32,
+    pub initial_pheromone: f32,
+    // amount of nearest planets an allied planet can send ships to
+    pub neighbourhood_size: usize,
+    pub look_ahead: usize,
+    pub seed: u64,
+}
+
+impl Default for AntsConfig {
+    fn default() -> Self {
+        AntsConfig {
+            ant_count: 20,
+            max_iterations: 50,
+            alpha: 1.0,
+            beta: 2.0,
+            evaporation: 0.1,
+            deposit: 1.0,
+            initial_pheromone: 1.0,
+            neighbourhood_size: 7,
+            look_ahead: 20,
+            seed: 0,
+        }
+    }
+}
+
+// a move an ant can choose, together with the ships needed to take the destination
+struct Candidate {
+    destination: PlanetId,
+    ship_count: i64,
+    // ships gained within the look ahead when the destination is taken, minus the ships sent
+    profit: f32,
+    heuristic: f32,
+}
+
+struct Origin {
+    planet_id: PlanetId,
+    candidates: Vec<Candidate>,
+}
 
+pub struct AntColonyOptimizationAlgorithm {
+    pub config: AntsConfig,
+    // pheromones[origin][destination], kept across turns
+    pheromones: Vec<Vec<f32>>,
+    rng: Rng,
 }
 
 impl AntColonyOptimizationAlgorithm {
-    pub fn calculate(&mut self, state: &mut State) -> Vec<Move> {
+    pub fn new(config: AntsConfig) -> Self {
+        let rng = Rng::new(config.seed);
+        AntColonyOptimizationAlgorithm { config, pheromones: Vec::new(), rng }
+    }
+
+    fn reset_pheromones(&mut self, planet_count: usize) {
+        self.pheromones = vec![vec![self.config.initial_pheromone; planet_count]; planet_count];
+    }
+
+    fn origins(&self, state: &State) -> Vec<Origin> {
+        let mut origins = Vec::new();
+        for origin_planet_id in 0..state.planet_names.len() {
+            let (origin_owner, _) = state.predict_planet(0, origin_planet_id);
+            if origin_owner != state.own_id() {
+                continue;
+            }
+            let surplus = (0..self.config.look_ahead).map(|ta| {
+                let (owner, fleet_size) = state.predict_planet(ta as i64, origin_planet_id);
+                if owner == state.own_id() {
+                    fleet_size
+                } else {
+                    -fleet_size
+                }
+            }).min().unwrap_or(0) - 1;
+            if surplus <= 0 {
+                continue;
+            }
+
+            let candidates = state.nearest_planets[origin_planet_id]
+                .iter()
+                .take(self.config.neighbourhood_size)
+                .filter_map(|(distance, destination_planet_id)| {
+                    let time_delta = distance.ceil() as i64;
+                    let (owner, fleet_size) = state.predict_planet(time_delta, *destination_planet_id);
+                    if owner == state.own_id() {
+                        return None;
+                    }
+                    let ship_count = fleet_size + 1;
+                    if ship_count > surplus {
+                        return None;
+                    }
+                    // taking an enemy planet also stops its growth for the enemy
+                    let value = if owner.is_none() { 1.0 } else { 2.0 };
+                    let turns_owned = (self.config.look_ahead as i64 - time_delta).max(0);
+                    let profit = value * turns_owned as f32 - ship_count as f32;
+                    if profit <= 0.0 {
+                        return None;
+                    }
+                    Some(Candidate {
+                        destination: *destination_planet_id,
+                        ship_count,
+                        profit,
+                        heuristic: profit / ship_count as f32,
+                    })
+                })
+                .collect::<Vec<_>>();
+            if !candidates.is_empty() {
+                origins.push(Origin { planet_id: origin_planet_id, candidates });
+            }
+        }
+        origins
+    }
+
+    // An ant visits the origins in random order and picks a destination for each of them,
+    // skipping destinations that are already targeted. Returns (origin index, candidate index)
+    // pairs.
+    fn construct_plan(&mut self, origins: &[Origin]) -> Vec<(usize, usize)> {
+        let mut order = (0..origins.len()).collect::<Vec<_>>();
+        for index in (1..order.len()).rev() {
+            let other = self.rng.range(0, index as i64 + 1) as usize;
+            order.swap(index, other);
+        }
+
+        let mut targeted = Vec::new();
+        let mut plan = Vec::new();
+        for origin_index in order {
+            let origin = &origins[origin_index];
+            let weights = origin.candidates.iter()
+                .map(|candidate| {
+                    if targeted.contains(&candidate.destination) {
+                        return 0.0;
+                    }
+                    let pheromone = self.pheromones[origin.planet_id][candidate.destination];
+                    pheromone.powf(self.config.alpha) * candidate.heuristic.powf(self.config.beta)
+                })
+                .collect::<Vec<_>>();
+            let total: f32 = weights.iter().sum();
+            if total <= 0.0 {
+                continue;
+            }
+
+            let mut remaining = self.rng.next_f32() * total;
+            let mut chosen = weights.len() - 1;
+            for (candidate_index, weight) in weights.iter().enumerate() {
+                if *weight > 0.0 && remaining < *weight {
+                    chosen = candidate_index;
+                    break;
+                }
+                remaining -= weight;
+            }
+            if weights[chosen] <= 0.0 {
+                continue;
+            }
+            targeted.push(origin.candidates[chosen].destination);
+            plan.push((origin_index, chosen));
+        }
+        plan
+    }
+}
+
+impl Default for AntColonyOptimizationAlgorithm {
+    fn default() -> Self {
+        AntColonyOptimizationAlgorithm::new(AntsConfig::default())
+    }
+}
+
+impl Strategy for AntColonyOptimizationAlgorithm {
+    fn name(&self) -> &'static str {
+        "ants"
+    }
+
+    fn setup(&mut self, state: &State) {
+        self.reset_pheromones(state.planet_names.len());
+    }
+
+    fn calculate(&mut self, state: &mut State, deadline: &Deadline) -> Vec<Move> {
+        if self.pheromones.len() != state.planet_names.len() {
+            self.reset_pheromones(state.planet_names.len());
+        }
+        for pheromone in self.pheromones.iter_mut().flatten() {
+            *pheromone = (*pheromone * (1.0 - self.config.evaporation)).max(MIN_PHEROMONE);
+        }
+
+        let origins = self.origins(state);
+        let score = |plan: &[(usize, usize)]| -> f32 {
+            plan.iter()
+                .map(|&(origin_index, candidate_index)| origins[origin_index].candidates[candidate_index].profit)
+                .sum()
+        };
+
+        // keeps improving the best plan until the deadline, so there always is an answer
+        let mut best_plan = Vec::new();
+        let mut best_score = 0.0;
+        for _ in 0..self.config.max_iterations {
+            if origins.is_empty() || deadline.expired() {
+                break;
+            }
+            let mut iteration_best = None;
+            for _ in 0..self.config.ant_count {
+                let plan = self.construct_plan(&origins);
+                let plan_score = score(&plan);
+                if iteration_best.as_ref().map_or(true, |(_, best)| plan_score > *best) {
+                    iteration_best = Some((plan, plan_score));
+                }
+            }
+            let Some((plan, plan_score)) = iteration_best else {
+                break;
+            };
+
+            for &(origin_index, candidate_index) in &plan {
+                let origin = &origins[origin_index];
+                let candidate = &origin.candidates[candidate_index];
+                let pheromone = &mut self.pheromones[origin.planet_id][candidate.destination];
+                let share = candidate.profit / (candidate.profit + candidate.ship_count as f32);
+                *pheromone = (*pheromone + self.config.deposit * share).min(MAX_PHEROMONE);
+            }
+            if plan_score > best_score {
+                best_score = plan_score;
+                best_plan = plan;
+            }
+        }
+
+        best_plan.into_iter()
+            .map(|(origin_index, candidate_index)| {
+                let origin = &origins[origin_index];
+                let candidate = &origin.candidates[candidate_index];
+                Move {
+                    origin: state.planet_names[origin.planet_id].clone(),
+                    destination: state.planet_names[candidate.destination].clone(),
+                    ship_count: candidate.ship_count,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AntColonyOptimizationAlgorithm, AntsConfig, MIN_PHEROMONE};
+    use crate::{
+        MAX_TURNS,
+        deadline::Deadline,
+        sim::{Game, generate_map},
+        state::State,
+        strategy::Strategy,
+        test_util::{expedition, input},
+    };
+
+    #[test]
+    fn pheromone_is_kept_and_evaporates_across_turns() {
+        let mut state = State::new(input(&[("a", Some(1), 30), ("b", None, 2), ("c", None, 3)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = AntColonyOptimizationAlgorithm::default();
+        algorithm.setup(&state);
+        let moves = algorithm.calculate(&mut state, &Deadline::unlimited());
+        assert_eq!(moves.len(), 1);
+        let destination = state.planet_map[&moves[0].destination];
+        let pheromones = algorithm.pheromones.clone();
+        assert!(pheromones[0][destination] > 0.9);
+        // c is no origin, its edges only evaporate
+        assert!((pheromones[2][1] - 0.9).abs() < 1e-6);
+
+        // without a search the next turns only evaporate what was laid
+        algorithm.config.max_iterations = 0;
+        algorithm.calculate(&mut state, &Deadline::unlimited());
+        assert!((algorithm.pheromones[0][destination] - pheromones[0][destination] * 0.9).abs() < 1e-6);
+        for _ in 0..100 {
+            algorithm.calculate(&mut state, &Deadline::unlimited());
+        }
+        assert!(algorithm.pheromones.iter().flatten().all(|&pheromone| pheromone == MIN_PHEROMONE));
+    }
+
+    // every origin keeps enough ships to stay ours for the whole look ahead, returns the amount
+    // of moves
+    fn assert_within_surplus(mut state: State, config: AntsConfig) -> usize {
+        let seed = config.seed;
+        let mut algorithm = AntColonyOptimizationAlgorithm::new(config);
+        algorithm.setup(&state);
+        let moves = algorithm.calculate(&mut state, &Deadline::unlimited());
+        for planned_move in &moves {
+            state.plan_move(planned_move).unwrap();
+        }
+        for planned_move in &moves {
+            let origin = state.planet_map[&planned_move.origin];
+            for turns_ahead in 0..algorithm.config.look_ahead as i64 {
+                let (owner, fleet_size) = state.predict_planet(turns_ahead, origin);
+                assert_eq!(owner, state.own_id(), "seed {seed}: {} lost at {turns_ahead}", planned_move.origin);
+                assert!(fleet_size >= 1, "seed {seed}: {} emptied at {turns_ahead}", planned_move.origin);
+            }
+        }
+        moves.len()
+    }
+
+    #[test]
+    fn moves_stay_within_the_surplus() {
+        let mut move_count = 0;
+        for seed in 0..10 {
+            // 20 enemies reach a in 4 turns, only 13 ships can leave, too few for b or c
+            let planets = [("a", Some(1), 30), ("b", None, 19), ("c", Some(2), 5)];
+            let state = State::new(input(&planets, vec![expedition(0, "a", 2, 20, 4)]), 1, MAX_TURNS);
+            let config = AntsConfig { seed, look_ahead: 60, ..AntsConfig::default() };
+            assert_eq!(assert_within_surplus(state, config), 0);
 
-        todo!()
+            let map = Game::new(generate_map(seed, 6), 200).unwrap().player_input(1);
+            move_count += assert_within_surplus(State::new(map, 1, MAX_TURNS), AntsConfig { seed, ..AntsConfig::default() });
+        }
+        assert!(move_count > 0);
     }
 }
@@ -0,0 +1,280 @@
+use std::{fs, process::exit, time::Duration};
+
+use itertools::Itertools;
+use prettytable::{Table, Row, Cell};
+use serde_json::{Map, Value};
+
+use enceladus_bot::{
+    bot::Bot,
+    config::{self, ConfigError},
+    harness::{ProcessBot, DEFAULT_TURN_TIMEOUT},
+    logging::{self, LogFilter},
+    sim::{self, Player},
+    strategy::build_strategy,
+    structs::{Input, DEFAULT_PLAYER_ID},
+};
+
+const USAGE: &str = "usage: tournament [--map FILE]... [--seeds N] [--max-turns N] [--timeout MS] [--log SPEC] ENTRANT ENTRANT...
+  ENTRANT is either STRATEGY[:KEY=VALUE[,KEY=VALUE]...], a TOML/JSON config file or cmd:COMMAND
+  to run an external bot";
+const DEFAULT_SEEDS: u64 = 10;
+const DEFAULT_MAX_TURNS: u64 = 200;
+const PLANET_PAIRS: usize = 10;
+const ELO_START: f64 = 1500.0;
+const ELO_K: f64 = 32.0;
+
+enum EntrantKind {
+    // a strategy with its parameters
+    Strategy { strategy: String, params: Map<String, Value> },
+    // an external bot, started with the given shell command
+    Process { command: String },
+}
+
+// every game gets a fresh instance of an entrant
+struct Entrant {
+    label: String,
+    kind: EntrantKind,
+}
+
+impl Entrant {
+    fn parse(spec: &str) -> Result<Self, ConfigError> {
+        let label = spec.to_string();
+        if let Some(command) = spec.strip_prefix("cmd:") {
+            return Ok(Entrant { label, kind: EntrantKind::Process { command: command.to_string() } });
+        }
+        if spec.ends_with(".toml") || spec.ends_with(".json") {
+            let mut params = config::load_config_file(spec)?;
+            let Some(Value::String(strategy)) = params.remove("strategy") else {
+                return Err(ConfigError::InvalidArgument(format!("{spec} does not name a strategy")));
+            };
+            build_strategy(&strategy, params.clone())?;
+            return Ok(Entrant { label, kind: EntrantKind::Strategy { strategy, params } });
+        }
+
+        let (strategy, raw_params) = spec.split_once(':').unwrap_or((spec, ""));
+        let params: Map<_, _> = raw_params.split(',')
+            .filter(|param| !param.is_empty())
+            .map(config::parse_param)
+            .collect::<Result<_, _>>()?;
+        build_strategy(strategy, params.clone())?;
+        Ok(Entrant { label, kind: EntrantKind::Strategy { strategy: strategy.to_string(), params } })
+    }
+
+    fn player(&self, turn_timeout: Duration, max_turns: u64) -> Result<Box<dyn Player>, String> {
+        match &self.kind {
+            EntrantKind::Strategy { strategy, params } => {
+                let strategy = build_strategy(strategy, params.clone())
+                    .expect("entrant was validated before the tournament started");
+                Ok(Box::new(Bot::new(strategy, DEFAULT_PLAYER_ID, max_turns)))
+            },
+            EntrantKind::Process { command } => {
+                let bot = ProcessBot::spawn(command, turn_timeout)
+                    .map_err(|err| format!("could not start '{command}': {err}"))?;
+                Ok(Box::new(bot))
+            },
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Score {
+    wins: u32,
+    losses: u32,
+    draws: u32,
+}
+
+struct Tournament {
+    entrants: Vec<Entrant>,
+    maps: Vec<(String, Input)>,
+    max_turns: u64,
+    turn_timeout: Duration,
+    // results[a][b] are the results of entrant a against entrant b
+    results: Vec<Vec<Score>>,
+    ratings: Vec<f64>,
+    game_lengths: Vec<u64>,
+}
+
+impl Tournament {
+    fn run(&mut self) {
+        let pairings = (0..self.entrants.len()).tuple_combinations::<(_, _)>().collect_vec();
+        for (map_name, map) in self.maps.clone() {
+            for &(a, b) in &pairings {
+                // every pairing is played from both starting positions
+                for (first, second) in [(a, b), (b, a)] {
+                    self.play(&map_name, map.clone(), first, second);
+                }
+            }
+        }
+    }
+
+    fn play(&mut self, map_name: &str, map: Input, first: usize, second: usize) {
+        let players = self.entrants[first].player(self.turn_timeout, self.max_turns)
+            .and_then(|first_player| Ok((first_player, self.entrants[second].player(self.turn_timeout, self.max_turns)?)));
+        let (mut first_player, mut second_player) = match players {
+            Ok(players) => players,
+            Err(err) => {
+                eprintln!("{err}");
+                exit(1);
+            }
+        };
+        let result = {
+            let mut players: [&mut dyn Player; 2] = [first_player.as_mut(), second_player.as_mut()];
+            sim::run_match(map, &mut players, self.max_turns)
+                .expect("maps were validated when they were loaded")
+        };
+        for (player_id, reason) in &result.forfeits {
+            let entrant = if *player_id == 1 { first } else { second };
+            eprintln!("{} forfeited on map {map_name}: {reason}", self.entrants[entrant].label);
+        }
+
+        let first_score = match result.winner {
+            Some(1) => {
+                self.results[first][second].wins += 1;
+                self.results[second][first].losses += 1;
+                1.0
+            },
+            Some(_) => {
+                self.results[first][second].losses += 1;
+                self.results[second][first].wins += 1;
+                0.0
+            },
+            None => {
+                self.results[first][second].draws += 1;
+                self.results[second][first].draws += 1;
+                0.5
+            },
+        };
+        self.update_ratings(first, second, first_score);
+        self.game_lengths.push(result.turns);
+        eprintln!(
+            "{map_name}: {} vs {} -> {:?} after {} turns",
+            self.entrants[first].label, self.entrants[second].label, result.winner, result.turns
+        );
+    }
+
+    fn update_ratings(&mut self, first: usize, second: usize, first_score: f64) {
+        let expected = 1.0 / (1.0 + 10f64.powf((self.ratings[second] - self.ratings[first]) / 400.0));
+        let delta = ELO_K * (first_score - expected);
+        self.ratings[first] += delta;
+        self.ratings[second] -= delta;
+    }
+
+    fn print_report(&self) {
+        let mut matrix = Table::new();
+        let mut header = vec![Cell::new("W/L/D")];
+        header.extend(self.entrants.iter().map(|entrant| Cell::new(&entrant.label)));
+        matrix.set_titles(Row::new(header));
+        for (index, entrant) in self.entrants.iter().enumerate() {
+            let mut row = vec![Cell::new(&entrant.label)];
+            for (other, score) in self.results[index].iter().enumerate() {
+                if other == index {
+                    row.push(Cell::new("-"));
+                } else {
+                    row.push(Cell::new(&format!("{}/{}/{}", score.wins, score.losses, score.draws)));
+                }
+            }
+            matrix.add_row(Row::new(row));
+        }
+        println!("{matrix}");
+
+        let mut ranking = Table::new();
+        ranking.set_titles(Row::new(vec![
+            Cell::new("entrant"), Cell::new("elo"), Cell::new("wins"), Cell::new("losses"), Cell::new("draws"),
+        ]));
+        let order = (0..self.entrants.len())
+            .sorted_by(|&a, &b| self.ratings[b].total_cmp(&self.ratings[a]))
+            .collect_vec();
+        for index in order {
+            let total = self.results[index].iter().fold(Score::default(), |acc, score| Score {
+                wins: acc.wins + score.wins,
+                losses: acc.losses + score.losses,
+                draws: acc.draws + score.draws,
+            });
+            ranking.add_row(Row::new(vec![
+                Cell::new(&self.entrants[index].label),
+                Cell::new(&format!("{:.0}", self.ratings[index])),
+                Cell::new(&total.wins.to_string()),
+                Cell::new(&total.losses.to_string()),
+                Cell::new(&total.draws.to_string()),
+            ]));
+        }
+        println!("{ranking}");
+
+        if !self.game_lengths.is_empty() {
+            let average = self.game_lengths.iter().sum::<u64>() as f64 / self.game_lengths.len() as f64;
+            println!("games played: {}, average game length: {average:.1} turns", self.game_lengths.len());
+        }
+    }
+}
+
+fn parse_number(value: Option<String>, flag: &str) -> Result<u64, ConfigError> {
+    value.as_deref()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ConfigError::InvalidArgument(format!("{flag} expects a number")))
+}
+
+fn parse_tournament(args: impl IntoIterator<Item = String>) -> Result<Tournament, ConfigError> {
+    let mut entrants = Vec::new();
+    let mut maps = Vec::new();
+    let mut seeds = None;
+    let mut max_turns = DEFAULT_MAX_TURNS;
+    let mut turn_timeout = DEFAULT_TURN_TIMEOUT;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--map expects a file".to_string()))?;
+                let map: Input = serde_json::from_str(&fs::read_to_string(&path)?)?;
+                if let Err(err) = sim::Game::new(map.clone(), DEFAULT_MAX_TURNS) {
+                    return Err(ConfigError::InvalidArgument(format!("map {path}: {err}")));
+                }
+                maps.push((path, map));
+            },
+            "--seeds" => seeds = Some(parse_number(args.next(), "--seeds")?),
+            "--max-turns" => max_turns = parse_number(args.next(), "--max-turns")?,
+            "--timeout" => turn_timeout = Duration::from_millis(parse_number(args.next(), "--timeout")?),
+            // the bots are quiet unless asked for, their logs would drown the report
+            "--log" => {
+                let spec = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--log expects a spec".to_string()))?;
+                logging::init(LogFilter::parse(&spec)?, None)?;
+            },
+            _ if arg.starts_with('-') => return Err(ConfigError::InvalidArgument(arg)),
+            _ => entrants.push(Entrant::parse(&arg)?),
+        }
+    }
+
+    if entrants.len() < 2 {
+        return Err(ConfigError::InvalidArgument("at least two entrants are needed".to_string()));
+    }
+    // generated maps are only added by default when no map files are given
+    let seeds = seeds.unwrap_or(if maps.is_empty() { DEFAULT_SEEDS } else { 0 });
+    for seed in 0..seeds {
+        maps.push((format!("seed {seed}"), sim::generate_map(seed, PLANET_PAIRS)));
+    }
+
+    let entrant_count = entrants.len();
+    Ok(Tournament {
+        entrants,
+        maps,
+        max_turns,
+        turn_timeout,
+        results: vec![vec![Score::default(); entrant_count]; entrant_count],
+        ratings: vec![ELO_START; entrant_count],
+        game_lengths: Vec::new(),
+    })
+}
+
+fn main() {
+    let mut tournament = match parse_tournament(std::env::args().skip(1)) {
+        Ok(tournament) => tournament,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            exit(1);
+        }
+    };
+    tournament.run();
+    tournament.print_report();
+}
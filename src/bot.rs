@@ -0,0 +1,222 @@
+use std::time::Instant;
+
+use log::{Level, info, log_enabled, warn};
+
+use crate::{
+    deadline::Deadline,
+    error::BotError,
+    graph_export::{ExportGraph, GraphExportOptions, write_graph_or_warn},
+    metrics::{Metrics, TurnMetrics, millis},
+    state::{PREDICTIONS_TARGET, State},
+    strategy::{Strategy, fallback_strategy},
+    outcome::{self, GameSummary},
+    structs::{GameSituation, Input, Move, PlayerId},
+    validate::validate_moves,
+};
+
+// Keeps the state of a single game and lets the strategy answer every turn
+pub struct Bot {
+    pub state: Option<State>,
+    pub strategy: Box<dyn Strategy>,
+    pub player_id: PlayerId,
+    // turn limit of the game
+    pub max_turns: u64,
+    // cheap strategy for turns where the budget is used up before planning starts
+    pub fallback: Box<dyn Strategy>,
+    pub metrics: Metrics,
+    // as evaluated at the start of the last turn
+    pub situation: GameSituation,
+    // the game is decided and the fallback plays it out
+    pub finishing: bool,
+    // the planet graph with the moves of the turn is exported when set
+    pub graph_export: Option<GraphExportOptions>,
+}
+
+impl Bot {
+    pub fn new(strategy: Box<dyn Strategy>, player_id: PlayerId, max_turns: u64) -> Self {
+        Bot {
+            state: None,
+            strategy,
+            player_id,
+            max_turns,
+            fallback: fallback_strategy(),
+            metrics: Metrics::default(),
+            situation: GameSituation::Ongoing,
+            finishing: false,
+            graph_export: None,
+        }
+    }
+
+    pub fn enable_graph_export(&mut self, options: GraphExportOptions) {
+        self.strategy.enable_graph_export(options.clone());
+        self.graph_export = Some(options);
+    }
+
+    // Parses a line of input. A line that can not be parsed still is a turn of the server, so
+    // the game moves on without it.
+    pub fn parse_input(&mut self, line: &str) -> Result<Input, BotError> {
+        serde_json::from_str(line).map_err(|err| {
+            self.skip_turn();
+            err.into()
+        })
+    }
+
+    // A turn of the server that could not be played. The turn counter has to follow the server,
+    // otherwise later expeditions are stored at the wrong turn. Before the first turn there is
+    // no state yet to move on.
+    pub fn skip_turn(&mut self) {
+        if let Some(state) = &mut self.state {
+            state.tick();
+        }
+    }
+
+    pub fn play_turn(&mut self, input: Input, deadline: Deadline) -> Result<Vec<Move>, BotError> {
+        let update_start = Instant::now();
+        let state = match &mut self.state {
+            Some(state) => {
+                if let Err(err) = state.update(input) {
+                    self.skip_turn();
+                    return Err(err.into());
+                }
+                state
+            },
+            None => {
+                let state = self.state.insert(State::new(input, self.player_id, self.max_turns));
+                self.strategy.setup(state);
+                state
+            },
+        };
+
+        let situation = state.check_gameover();
+        if situation != self.situation {
+            info!(target: "game", "game is {situation:?} at turn {}", state.turn);
+            self.situation = situation;
+        }
+        let finishing = state.is_decided();
+        if finishing && !self.finishing {
+            info!(target: "game", "game is decided at turn {}, finishing it with {}", state.turn, self.fallback.name());
+        }
+        self.finishing = finishing;
+
+        let update_time = update_start.elapsed();
+        if log_enabled!(target: PREDICTIONS_TARGET, Level::Trace) {
+            state.debug_print_predictions();
+        }
+
+        let mut turn_metrics = TurnMetrics { turn: state.turn, update_ms: millis(update_time), ..Default::default() };
+        state.take_predict_calls();
+        let calculate_start = Instant::now();
+        let moves = if situation != GameSituation::Ongoing {
+            // nothing left to plan, for example when we are out but the others play on
+            Vec::new()
+        } else if deadline.expired() {
+            warn!("no time left after {}ms, using fallback", deadline.elapsed().as_millis());
+            self.fallback.calculate(state, &deadline.hard_only())
+        } else if finishing {
+            // winning does not need an expensive plan anymore
+            self.fallback.calculate(state, &deadline)
+        } else {
+            let moves = self.strategy.calculate(state, &deadline);
+            self.strategy.report_metrics(&mut turn_metrics);
+            moves
+        };
+        turn_metrics.calculate_ms = millis(calculate_start.elapsed());
+        turn_metrics.predict_calls = state.take_predict_calls();
+
+        let (moves, corrections) = validate_moves(state, moves);
+        for correction in corrections {
+            warn!("invalid move: {correction}");
+        }
+        // predictions for the rest of the turn include what is actually sent
+        state.discard_planned();
+        for planned_move in &moves {
+            state.plan_move(planned_move).ok();
+        }
+        state.commit_planned();
+        if let Some(options) = self.graph_export.as_ref().filter(|options| options.wants(state.turn)) {
+            let graph = ExportGraph::from_planets(state, &moves);
+            write_graph_or_warn(options, &graph, &format!("turn-{:04}-planets", state.turn));
+        }
+        if deadline.hard_expired() {
+            warn!("turn took {}ms, more than the hard limit", deadline.elapsed().as_millis());
+        }
+        turn_metrics.total_ms = millis(deadline.elapsed());
+        self.metrics.record(turn_metrics);
+        state.tick();
+        Ok(moves)
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(state) = &self.state {
+            self.strategy.teardown(state);
+            // the server stops sending turns once the game is over, so the last observation
+            // is judged as a finished game
+            let Input { planets, expeditions } = &state.current_state;
+            let situation = outcome::situation(planets, expeditions, self.player_id, true);
+            info!(target: "game", "{}", GameSummary::new(planets, expeditions, situation, state.turn));
+        }
+        if !self.metrics.turns.is_empty() {
+            info!(target: "metrics", "metrics of {}\n{}", self.strategy.name(), self.metrics.summary());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Map, json};
+
+    use super::Bot;
+    use crate::{MAX_TURNS, deadline::Deadline, strategy::build_strategy, structs::GameSituation};
+
+    fn input_line(expeditions: &[(u64, i64)]) -> String {
+        let expeditions = expeditions.iter()
+            .map(|&(id, turns_remaining)| json!({
+                "id": id,
+                "ship_count": 5,
+                "origin": "c",
+                "destination": "b",
+                "owner": 2,
+                "turns_remaining": turns_remaining,
+            }))
+            .collect::<Vec<_>>();
+        json!({
+            "planets": [
+                { "ship_count": 10, "x": 0.0, "y": 0.0, "owner": 1, "name": "a" },
+                { "ship_count": 5, "x": 10.0, "y": 0.0, "owner": null, "name": "b" },
+                { "ship_count": 10, "x": 20.0, "y": 0.0, "owner": 2, "name": "c" },
+            ],
+            "expeditions": expeditions,
+        }).to_string()
+    }
+
+    fn play(bot: &mut Bot, line: &str) {
+        let input = bot.parse_input(line).unwrap();
+        bot.play_turn(input, Deadline::unlimited()).unwrap();
+    }
+
+    #[test]
+    fn unreadable_turns_are_counted() {
+        let mut bot = Bot::new(build_strategy("simple", Map::new()).unwrap(), 1, MAX_TURNS);
+        play(&mut bot, &input_line(&[]));
+        play(&mut bot, &input_line(&[(0, 4)]));
+        assert!(bot.parse_input("{ not json").is_err());
+        // both expeditions arrive at turn 5
+        play(&mut bot, &input_line(&[(0, 2), (1, 2)]));
+        let state = bot.state.as_ref().unwrap();
+        let arrivals = state.hostile_arrivals().into_iter().collect::<Vec<_>>();
+        assert_eq!(arrivals, vec![((1, 1), 10)]);
+    }
+
+    #[test]
+    fn bot_plays_up_to_the_turn_limit_of_the_game() {
+        let mut bot = Bot::new(build_strategy("simple", Map::new()).unwrap(), 1, 1000);
+        let line = input_line(&[]);
+        for _ in 0..510 {
+            play(&mut bot, &line);
+        }
+        let input = bot.parse_input(&line).unwrap();
+        let moves = bot.play_turn(input, Deadline::unlimited()).unwrap();
+        assert_eq!(bot.situation, GameSituation::Ongoing);
+        assert!(!moves.is_empty());
+    }
+}
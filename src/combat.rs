@@ -0,0 +1,93 @@
+use crate::structs::PlayerId;
+
+// Resolves the fight on a planet the way the server does. Every force is merged with the other
+// forces of the same owner (the garrison of a neutral planet is a force without owner). Then,
+// as long as more than one force is left, the smallest one is removed and its ships are
+// subtracted from all the others, and forces without ships are dropped. Effectively the largest
+// force survives with the difference to the second largest, whatever the amount of players.
+//
+// When the largest forces are equal nobody survives and the planet becomes neutral without
+// ships, the previous owner does not keep it. A force that is alone keeps the planet, even
+// without ships. Predictions can contain forces below zero (more ships leaving than there
+// were), those fight as empty forces.
+pub fn resolve_combat(
+    garrison: (Option<PlayerId>, i64),
+    arrivals: impl IntoIterator<Item = (Option<PlayerId>, i64)>,
+) -> (Option<PlayerId>, i64) {
+    let mut forces: Vec<(Option<PlayerId>, i64)> = vec![garrison];
+    for (owner, amount) in arrivals {
+        match forces.iter_mut().find(|(force_owner, _)| *force_owner == owner) {
+            Some((_, force_amount)) => *force_amount += amount,
+            None => forces.push((owner, amount)),
+        }
+    }
+    if let [(owner, amount)] = forces.as_slice() {
+        return (*owner, (*amount).max(0));
+    }
+
+    let mut amounts = forces.iter().map(|(_, amount)| (*amount).max(0)).collect::<Vec<_>>();
+    amounts.sort_unstable_by(|a, b| b.cmp(a));
+    let (largest, second) = (amounts[0], amounts[1]);
+    if largest == second {
+        return (None, 0);
+    }
+    let owner = forces.iter()
+        .find(|(_, amount)| (*amount).max(0) == largest)
+        .map(|(owner, _)| *owner)
+        .expect("largest force exists");
+    (owner, largest - second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_combat;
+
+    #[test]
+    fn garrison_without_arrivals_keeps_planet() {
+        assert_eq!(resolve_combat((Some(1), 12), []), (Some(1), 12));
+        assert_eq!(resolve_combat((None, 5), []), (None, 5));
+        assert_eq!(resolve_combat((Some(2), 0), []), (Some(2), 0));
+    }
+
+    #[test]
+    fn reinforcements_merge_with_garrison() {
+        assert_eq!(resolve_combat((Some(1), 10), [(Some(1), 5), (Some(1), 3)]), (Some(1), 18));
+    }
+
+    #[test]
+    fn attacker_takes_neutral_planet() {
+        assert_eq!(resolve_combat((None, 10), [(Some(1), 15)]), (Some(1), 5));
+        assert_eq!(resolve_combat((None, 10), [(Some(1), 7)]), (None, 3));
+    }
+
+    #[test]
+    fn arrivals_of_one_player_fight_together() {
+        assert_eq!(resolve_combat((Some(2), 10), [(Some(1), 6), (Some(1), 6)]), (Some(1), 2));
+    }
+
+    #[test]
+    fn largest_force_keeps_difference_with_second() {
+        let forces = [(Some(1), 20), (Some(2), 12), (Some(3), 5)];
+        assert_eq!(resolve_combat((None, 8), forces), (Some(1), 8));
+        assert_eq!(resolve_combat((Some(3), 30), forces), (Some(3), 15));
+    }
+
+    #[test]
+    fn tie_between_largest_forces_neutralizes_planet() {
+        assert_eq!(resolve_combat((Some(1), 10), [(Some(2), 10)]), (None, 0));
+        assert_eq!(resolve_combat((None, 3), [(Some(1), 9), (Some(2), 9)]), (None, 0));
+        assert_eq!(resolve_combat((Some(1), 4), [(Some(2), 4), (Some(3), 4)]), (None, 0));
+    }
+
+    #[test]
+    fn tie_between_smaller_forces_does_not_matter() {
+        assert_eq!(resolve_combat((Some(1), 15), [(Some(2), 5), (Some(3), 5)]), (Some(1), 10));
+        assert_eq!(resolve_combat((None, 5), [(Some(1), 5), (Some(2), 11)]), (Some(2), 6));
+    }
+
+    #[test]
+    fn negative_forces_fight_as_empty() {
+        assert_eq!(resolve_combat((Some(1), -4), [(Some(2), 6)]), (Some(2), 6));
+        assert_eq!(resolve_combat((Some(1), -4), []), (Some(1), 0));
+    }
+}
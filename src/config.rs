@@ -0,0 +1,257 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+use serde_json::{Map, Value};
+
+use crate::{MAX_TURNS, graph_export::GraphExportOptions, logging::LogFilter, strategy::DEFAULT_STRATEGY, structs::{PlayerId, DEFAULT_PLAYER_ID}};
+
+pub const USAGE: &str = "usage: enceladus-bot [STRATEGY] [--config FILE] [--param KEY=VALUE]... [--player-id ID] [--max-turns TURNS]
+                     [--record FILE] [--log SPEC] [--log-file FILE] [--metrics FILE] [--export-graphs DIR [--export-turns TURNS]]
+       enceladus-bot replay FILE [STRATEGY] [--config FILE] [--param KEY=VALUE]... [--log SPEC]
+                     [--export-graphs DIR [--export-turns TURNS]]
+  SPEC is a comma separated list of levels, optionally per target: info,state=debug,direct_flow=trace
+  prediction tables are logged with state::predictions=trace
+  replay plays with the recorded strategy and parameters, given ones override them
+  graphs are exported as Graphviz DOT and vis.js JSON, for every turn or for TURNS like 10,20-25";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    InvalidArgument(String),
+    UnknownStrategy(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config file: {err}"),
+            ConfigError::Toml(err) => write!(f, "invalid TOML config: {err}"),
+            ConfigError::Json(err) => write!(f, "invalid strategy parameters: {err}"),
+            ConfigError::InvalidArgument(arg) => write!(f, "invalid argument: {arg}"),
+            ConfigError::UnknownStrategy(name) => write!(f, "unknown strategy '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Toml(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(err: serde_json::Error) -> Self {
+        ConfigError::Json(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub strategy: String,
+    // false when no strategy was given and the default one is used
+    pub strategy_chosen: bool,
+    pub player_id: PlayerId,
+    // turn limit of the game
+    pub max_turns: u64,
+    // strategy parameters, deserialized into the config struct of the chosen strategy
+    pub params: Map<String, Value>,
+    // file to write a replay of the game to
+    pub record: Option<PathBuf>,
+    pub log: LogFilter,
+    // file to write the log to as JSON lines, next to stderr
+    pub log_file: Option<PathBuf>,
+    // file to write the turn metrics to when the game is over, they are logged otherwise
+    pub metrics: Option<PathBuf>,
+    pub graph_export: Option<GraphExportOptions>,
+}
+
+// Parses the command line (without the program name). Parameters are merged in order:
+// the config file first, then every `--param` override on top of it. The strategy given
+// as positional argument takes precedence over a `strategy` key in the config file.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, ConfigError> {
+    let mut strategy = None;
+    let mut config_file = None;
+    let mut overrides = Vec::new();
+    let mut player_id = DEFAULT_PLAYER_ID;
+    let mut max_turns = MAX_TURNS;
+    let mut record = None;
+    let mut log = LogFilter::default();
+    let mut log_file = None;
+    let mut metrics = None;
+    let mut graph_dir = None;
+    let mut graph_turns = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" | "-c" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--config expects a file".to_string()))?;
+                config_file = Some(path);
+            },
+            "--param" | "-p" => {
+                let param = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--param expects KEY=VALUE".to_string()))?;
+                overrides.push(parse_param(&param)?);
+            },
+            "--player-id" => {
+                player_id = args.next()
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| ConfigError::InvalidArgument("--player-id expects a number".to_string()))?;
+            },
+            "--max-turns" => {
+                max_turns = args.next()
+                    .and_then(|turns| turns.parse().ok())
+                    .ok_or_else(|| ConfigError::InvalidArgument("--max-turns expects a number".to_string()))?;
+            },
+            "--record" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--record expects a file".to_string()))?;
+                record = Some(PathBuf::from(path));
+            },
+            "--log" => {
+                let spec = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--log expects a spec".to_string()))?;
+                log = LogFilter::parse(&spec)?;
+            },
+            "--log-file" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--log-file expects a file".to_string()))?;
+                log_file = Some(PathBuf::from(path));
+            },
+            "--metrics" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--metrics expects a file".to_string()))?;
+                metrics = Some(PathBuf::from(path));
+            },
+            "--export-graphs" => {
+                let path = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--export-graphs expects a directory".to_string()))?;
+                graph_dir = Some(PathBuf::from(path));
+            },
+            "--export-turns" => {
+                let spec = args.next()
+                    .ok_or_else(|| ConfigError::InvalidArgument("--export-turns expects turns".to_string()))?;
+                graph_turns = Some(GraphExportOptions::parse_turns(&spec)?);
+            },
+            _ if arg.starts_with('-') => return Err(ConfigError::InvalidArgument(arg)),
+            _ if strategy.is_none() => strategy = Some(arg),
+            _ => return Err(ConfigError::InvalidArgument(arg)),
+        }
+    }
+
+    let mut params = match config_file {
+        Some(path) => load_config_file(path)?,
+        None => Map::new(),
+    };
+    let file_strategy = match params.remove("strategy") {
+        Some(Value::String(name)) => Some(name),
+        Some(other) => return Err(ConfigError::InvalidArgument(format!("strategy = {other}"))),
+        None => None,
+    };
+    params.extend(overrides);
+    let graph_export = match (graph_dir, graph_turns) {
+        (Some(dir), turns) => Some(GraphExportOptions { dir, turns: turns.unwrap_or_default() }),
+        (None, Some(_)) => return Err(ConfigError::InvalidArgument("--export-turns without --export-graphs".to_string())),
+        (None, None) => None,
+    };
+
+    let strategy = strategy.or(file_strategy);
+    Ok(Args {
+        strategy_chosen: strategy.is_some(),
+        strategy: strategy.unwrap_or_else(|| DEFAULT_STRATEGY.to_string()),
+        player_id,
+        max_turns,
+        params,
+        record,
+        log,
+        log_file,
+        metrics,
+        graph_export,
+    })
+}
+
+// Reads a flat table of strategy parameters, JSON if the extension says so, TOML otherwise.
+pub fn load_config_file(path: impl AsRef<Path>) -> Result<Map<String, Value>, ConfigError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let value: Value = if path.extension().map_or(false, |ext| ext == "json") {
+        serde_json::from_str(&contents)?
+    } else {
+        toml::from_str(&contents)?
+    };
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => Err(ConfigError::InvalidArgument(format!("{} does not contain a table", path.display()))),
+    }
+}
+
+// `key=value`, where value is parsed as JSON when possible (numbers, booleans, ...) and kept
+// as a string otherwise
+pub fn parse_param(param: &str) -> Result<(String, Value), ConfigError> {
+    let Some((key, raw_value)) = param.split_once('=') else {
+        return Err(ConfigError::InvalidArgument(param.to_string()));
+    };
+    let value = serde_json::from_str(raw_value)
+        .unwrap_or_else(|_| Value::String(raw_value.to_string()));
+    Ok((key.trim().to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use serde_json::{Value, json};
+
+    use super::{ConfigError, parse_args, parse_param};
+    use crate::strategy::DEFAULT_STRATEGY;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments_take_precedence_over_the_config_file() {
+        let path = env::temp_dir().join(format!("enceladus-config-{}.toml", std::process::id()));
+        fs::write(&path, "strategy = \"simple\"\nlook_ahead = 12\nneighbourhood_size = 3\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let from_file = parse_args(args(&["--config", path])).unwrap();
+        assert_eq!((from_file.strategy.as_str(), from_file.strategy_chosen), ("simple", true));
+        assert_eq!(from_file.params, *json!({ "look_ahead": 12, "neighbourhood_size": 3 }).as_object().unwrap());
+
+        let overridden = parse_args(args(&["neighbourhood", "--config", path, "-p", "look_ahead=20", "--param", "look_ahead=25"])).unwrap();
+        fs::remove_file(path).ok();
+        assert_eq!(overridden.strategy, "neighbourhood");
+        assert_eq!(overridden.params, *json!({ "look_ahead": 25, "neighbourhood_size": 3 }).as_object().unwrap());
+    }
+
+    #[test]
+    fn default_strategy_is_not_chosen() {
+        let args = parse_args(args(&[])).unwrap();
+        assert_eq!((args.strategy.as_str(), args.strategy_chosen), (DEFAULT_STRATEGY, false));
+        assert!(args.params.is_empty());
+    }
+
+    #[test]
+    fn params_are_parsed_as_json_when_possible() {
+        assert_eq!(parse_param("look_ahead=20").unwrap(), ("look_ahead".to_string(), json!(20)));
+        assert_eq!(parse_param(" name =x=y").unwrap(), ("name".to_string(), Value::String("x=y".to_string())));
+    }
+
+    #[test]
+    fn malformed_params_are_rejected() {
+        assert!(matches!(parse_param("look_ahead"), Err(ConfigError::InvalidArgument(arg)) if arg == "look_ahead"));
+        assert!(matches!(parse_args(args(&["--param", "look_ahead"])), Err(ConfigError::InvalidArgument(_))));
+        assert!(matches!(parse_args(args(&["--param"])), Err(ConfigError::InvalidArgument(_))));
+    }
+}
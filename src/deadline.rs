@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use crate::{MAX_DURATION, HARD_MAX_DURATION};
+
+// Time budget of a single turn. Strategies plan until the soft limit has passed and then
+// return the best moves they have found so far, the hard limit is the turn timeout of the
+// server.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    start: Instant,
+    soft: Duration,
+    hard: Duration,
+}
+
+impl Deadline {
+    pub fn new(start: Instant, soft: Duration, hard: Duration) -> Self {
+        Deadline { start, soft, hard: hard.max(soft) }
+    }
+
+    // the default budget of a turn that started at `start`
+    pub fn for_turn(start: Instant) -> Self {
+        Deadline::new(start, Duration::from_millis(MAX_DURATION), Duration::from_millis(HARD_MAX_DURATION))
+    }
+
+    // a deadline that never expires, for when the outcome must not depend on timing
+    pub fn unlimited() -> Self {
+        Deadline::new(Instant::now(), Duration::MAX, Duration::MAX)
+    }
+
+    // the same deadline, but planning is allowed to continue up to the hard limit
+    pub fn hard_only(&self) -> Self {
+        Deadline { soft: self.hard, ..*self }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // time left until the soft limit
+    pub fn remaining(&self) -> Duration {
+        self.soft.saturating_sub(self.elapsed())
+    }
+
+    pub fn expired(&self) -> bool {
+        self.elapsed() >= self.soft
+    }
+
+    pub fn hard_expired(&self) -> bool {
+        self.elapsed() >= self.hard
+    }
+}
@@ -1,151 +1,373 @@
-use std::{collections::BTreeMap, fs::File};
-use std::io::Write;
+use std::{collections::{BTreeMap, BTreeSet}, time::{Duration, Instant}};
 
+use log::{Level, debug, log_enabled, trace, warn};
 use mcmf::{GraphBuilder, Vertex, Capacity, Cost};
-use serde_json::json;
 use smallvec::SmallVec;
 
-use crate::{structs::{Move, PlanetId}, state::State};
+use serde::Deserialize;
 
-const LOOK_AHEAD: usize = 40;
-const IDLE_PENALTY_COST: i32 = 1000;
+use crate::{
+    structs::{Move, PlanetId},
+    state::State,
+    strategy::{Strategy, fallback_strategy},
+    deadline::Deadline,
+    metrics::{GraphMetrics, TurnMetrics},
+    graph_export::{ExportGraph, GraphExportOptions, write_graph_or_warn},
+};
 
-#[allow(dead_code)]
-pub struct Flow1Algorithm {
-    pub id: Option<u8>,
-}
-
-#[allow(dead_code)]
-fn write_graph_to_file(graph_builder: &GraphBuilder<(i32, i32, i32)>) {
-    let mut id = 0;
-    let mut id_map = BTreeMap::new();
-    let mut nodes = Vec::new();
-    let mut edges = Vec::new();
-    for (begin, end, cap, cost) in &graph_builder.edge_list {
-        let start_node_id = *id_map.entry(begin).or_insert({
-            id += 1;
-            id
-        });
+// Solver throughput assumed until the first solve has been measured, on the slow side
+const INITIAL_EDGES_PER_MS: f64 = 20.0;
+// Share of the remaining time the solver is planned to take. The throughput is only an
+// estimate and the moves still have to be validated and sent afterwards.
+const SOLVE_BUDGET_SHARE: f64 = 0.5;
 
-        let end_node_id = *id_map.entry(end).or_insert({
-            id += 1;
-            id
-        });
+// The graph has an in and an out node for every planet and every time step up to the look ahead.
+// Ships flow from the source (our ships and their growth) along waiting and travel edges to the
+// sink, the solver picks the flow with the lowest total cost. Costs are integers per ship, a
+// travel edge costs one for every turn of the journey.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlowConfig {
+    // in turns, the last one that gets nodes, ships that arrive later go straight to the sink
+    pub look_ahead: usize,
+    // cost per ship that is still waiting on one of our planets at the end of the look ahead
+    pub idle_penalty_cost: i32,
+    // Cost per ship for a score of 1.0. The solver minimises the cost, so capture edges cost
+    // the negated scaled score: a positive score is a reward, a negative one a penalty for
+    // taking the planet.
+    pub score_scale: f32,
+    // factor on the score of planets reached beyond the look ahead, whose prediction is less
+    // certain and which the flow can not move on from
+    pub beyond_horizon_weight: f32,
+    // in turns: every turn up to `fine_turns` ahead is a time step with nodes, after that only
+    // every `coarse_step`th turn, arrivals in between wait for the next one. This keeps long
+    // look aheads tractable.
+    pub fine_turns: usize,
+    pub coarse_step: usize,
+    // in turns, how long the routes of a solution are followed before the graph is solved
+    // again, 1 solves it every turn. A plan that no longer fits the state is dropped earlier,
+    // and on turns without a hop of the plan the graph is solved again for the ships it left
+    // out.
+    pub replan_interval: usize,
+}
 
-        if cap.0 == i32::MAX {
-            edges.push(
-                json!({
-                    "from": start_node_id,
-                    "to": end_node_id,
-                    "label": format!("cap: {}\ncost: {}", "MAX", cost.0)
-                })
-            );
-        } else {
-            edges.push(
-                json!({
-                    "from": start_node_id,
-                    "to": end_node_id,
-                    "label": format!("cap: {}\ncost: {}", cap.0, cost.0)
-                })
-            );
-        }
-    }
-    for (key, value) in id_map.iter() {
-        if let Vertex::Source = key {
-            nodes.push(json!({
-                "id": value,
-                "label": format!("{key:?}"),
-                "x": -300.0,
-                "y": 1500.0,
-            }));
-            continue;
-        } else if let Vertex::Sink = key {
-            nodes.push(json!({
-                "id": value,
-                "label": format!("{key:?}"),
-                "x": 2000.0,
-                "y": 1500.0,
-            }));
-            continue;
+impl Default for FlowConfig {
+    fn default() -> Self {
+        FlowConfig {
+            look_ahead: 40,
+            idle_penalty_cost: 1000,
+            score_scale: 1000.0,
+            beyond_horizon_weight: 0.5,
+            fine_turns: 40,
+            coarse_step: 1,
+            replan_interval: 5,
         }
-        // nodes.push(format!("{{ \"id\":{value}, \"label\": \"{key:?}\" }}"));
-        let scale_factor = 200.0;
-        let Vertex::Node((planet_id, turns_ahead, in_out)) = key else {
-            todo!()
-        };
-        let mut x = *planet_id as f32 * scale_factor;
-        let mut y = *turns_ahead as f32 * scale_factor;
-        if *in_out == 1 {
-            x += 0.5 * scale_factor;
-            y += 0.5 * scale_factor;
+    }
+}
+
+impl FlowConfig {
+    // Upper bound of the edges of the graph for `state`: the waiting, growth and capture edges
+    // of every planet and the travel edges to its nearest planets at every time step, and the
+    // capture and sink edges of the arrivals beyond the look ahead, and the defence demands.
+    fn estimated_edges(&self, state: &State) -> usize {
+        let time_steps = self.time_steps();
+        state.nearest_planets.iter()
+            .flat_map(|nearest| nearest.iter())
+            .map(|(distance, _)| {
+                let beyond_horizon = time_steps.iter()
+                    .filter(|&&turns_ahead| turns_ahead + distance.ceil() as i32 > self.look_ahead as i32)
+                    .count();
+                time_steps.len() + 3 * beyond_horizon
+            })
+            .sum::<usize>()
+            + 3 * time_steps.len() * state.nearest_planets.len()
+            + state.hostile_arrivals().len()
+    }
+
+    // the turns ahead that get nodes, from 0 up to and including the look ahead
+    fn time_steps(&self) -> Vec<i32> {
+        let look_ahead = self.look_ahead as i32;
+        let fine_turns = (self.fine_turns as i32).min(look_ahead);
+        let mut time_steps = (0..=fine_turns).collect::<Vec<_>>();
+        time_steps.extend((fine_turns..look_ahead).step_by(self.coarse_step.max(1)).skip(1));
+        if fine_turns < look_ahead {
+            time_steps.push(look_ahead);
         }
-        nodes.push(json!({
-            "id": value,
-            "label": format!("{key:?}"),
-            "x": x,
-            "y": y,
-        }));
-        // graph_entries.push(format!("{value}(\"{key:?}\")"));
+        time_steps
     }
-    let mut node_file = File::create("./src/nodes.json").unwrap();
-    write!(&mut node_file, "{}", serde_json::to_string_pretty(&nodes).unwrap()).unwrap();
-    let mut edge_file = File::create("./src/edges.json").unwrap();
-    write!(&mut edge_file, "{}", serde_json::to_string_pretty(&edges).unwrap()).unwrap();
 
+    // the first time step at or after `turns_ahead`, `None` beyond the look ahead
+    fn time_step_at(&self, turns_ahead: i32) -> Option<i32> {
+        let look_ahead = self.look_ahead as i32;
+        let fine_turns = self.fine_turns as i32;
+        let coarse_step = self.coarse_step.max(1) as i32;
+        if turns_ahead > look_ahead {
+            return None;
+        }
+        if turns_ahead <= fine_turns {
+            return Some(turns_ahead);
+        }
+        let steps = (turns_ahead - fine_turns + coarse_step - 1) / coarse_step;
+        Some((fine_turns + steps * coarse_step).min(look_ahead))
+    }
+}
+
+// one leg of a planned route, the ships leave `origin` at `turn`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PlannedHop {
+    turn: i64,
+    origin: PlanetId,
+    destination: PlanetId,
+    ship_count: i64,
+}
+
+// The routes of the last solution, including the hops through planets at later turns
+#[derive(Debug, Clone)]
+struct FlowPlan {
+    solved_turn: i64,
+    // ordered by turn
+    hops: Vec<PlannedHop>,
+    // enemy ships the solution had to meet, by arrival turn and planet
+    hostile_arrivals: BTreeMap<(i64, PlanetId), i64>,
+}
+
+impl FlowPlan {
+    // The reason the plan can not be followed anymore, if any. The enemies that arrive at the
+    // planets of the hops must be the ones the plan was made for. Hops that are due must have
+    // their ships available now, later hops must start on a planet that is predicted to be ours
+    // or that an earlier hop is on its way to.
+    fn validate(&self, state: &State) -> Result<(), String> {
+        // enemies elsewhere do not change the routes
+        let touched = self.hops.iter()
+            .flat_map(|hop| [hop.origin, hop.destination])
+            .collect::<BTreeSet<_>>();
+        for (key, ship_count) in hostile_arrivals_by_turn(state) {
+            if touched.contains(&key.1) && self.hostile_arrivals.get(&key) != Some(&ship_count) {
+                return Err(format!("{ship_count} enemy ships arrive at planet {} at turn {}", key.1, key.0));
+            }
+        }
+
+        let mut available = BTreeMap::new();
+        for (index, hop) in self.hops.iter().enumerate() {
+            if hop.turn < state.turn {
+                return Err(format!("missed a hop at turn {}", hop.turn));
+            }
+            let (owner, fleet_size) = state.predict_planet(hop.turn - state.turn, hop.origin);
+            if hop.turn == state.turn {
+                let available = available.entry(hop.origin)
+                    .or_insert(if owner == state.own_id() { fleet_size } else { 0 });
+                *available -= hop.ship_count;
+                if *available < 0 {
+                    return Err(format!("not enough ships on planet {}", hop.origin));
+                }
+                continue;
+            }
+            let reinforced = self.hops[..index].iter()
+                .any(|other| other.destination == hop.origin && other.arrival_turn(state) <= hop.turn);
+            if owner != state.own_id() && !reinforced {
+                return Err(format!("planet {} is not ours at turn {}", hop.origin, hop.turn));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PlannedHop {
+    fn arrival_turn(&self, state: &State) -> i64 {
+        self.turn + state.distance(self.origin, self.destination).ceil() as i64
+    }
+}
+
+pub struct Flow1Algorithm {
+    pub config: FlowConfig,
+    // used when building the graph does not finish in time
+    fallback: Box<dyn Strategy>,
+    // the graph solved in the last turn, if any
+    last_graph: Option<GraphMetrics>,
+    // followed in the next turns, until it is due to be solved again or does not fit anymore
+    plan: Option<FlowPlan>,
+    graph_export: Option<GraphExportOptions>,
+    // measured in the last solve, sizes the next graph
+    edges_per_ms: f64,
 }
 
-#[allow(dead_code)]
 impl Flow1Algorithm {
-    pub fn calculate(&mut self, state: &mut State) -> Vec<Move> {
+    pub fn new(config: FlowConfig) -> Self {
+        Flow1Algorithm {
+            config,
+            fallback: fallback_strategy(),
+            last_graph: None,
+            plan: None,
+            graph_export: None,
+            edges_per_ms: INITIAL_EDGES_PER_MS,
+        }
+    }
+
+    // edges the solver is expected to get through in its share of the remaining time
+    fn solvable_edges(&self, remaining: Duration) -> f64 {
+        remaining.as_secs_f64() * 1000.0 * SOLVE_BUDGET_SHARE * self.edges_per_ms
+    }
+
+    // The configuration with the longest look ahead, up to the configured one, whose graph can
+    // be solved in time. `None` when not even a single turn ahead fits. The solver can not be
+    // interrupted, so the size of the graph is what keeps it within the deadline.
+    fn budgeted_config(&self, state: &State, remaining: Duration) -> Option<FlowConfig> {
+        let solvable_edges = self.solvable_edges(remaining);
+        (1..=self.config.look_ahead).rev()
+            .map(|look_ahead| FlowConfig { look_ahead, ..self.config.clone() })
+            .find(|config| config.estimated_edges(state) as f64 <= solvable_edges)
+    }
+
+    // The moves of the current plan that are due this turn, `None` when the graph has to be
+    // solved again. Ships that grew or were captured since the plan was made are not part of
+    // it, so a turn without a hop of the plan is solved again rather than sending nothing.
+    fn follow_plan(&mut self, state: &State) -> Option<Vec<Move>> {
+        let mut plan = self.plan.take()?;
+        if state.turn - plan.solved_turn >= self.config.replan_interval as i64 {
+            return None;
+        }
+        if let Err(reason) = plan.validate(state) {
+            debug!("dropping the plan of turn {}: {reason}", plan.solved_turn);
+            return None;
+        }
+        if plan.hops.iter().all(|hop| hop.turn != state.turn) {
+            return None;
+        }
+        let moves = take_due_moves(&mut plan, state);
+        self.plan = Some(plan);
+        Some(moves)
+    }
+}
+
+// the enemy arrivals of the state, by turn instead of turns ahead
+fn hostile_arrivals_by_turn(state: &State) -> BTreeMap<(i64, PlanetId), i64> {
+    state.hostile_arrivals().into_iter()
+        .map(|((turns_ahead, planet_id), ship_count)| ((state.turn + turns_ahead, planet_id), ship_count))
+        .collect()
+}
+
+// removes the hops of this turn from the plan
+fn take_due_moves(plan: &mut FlowPlan, state: &State) -> Vec<Move> {
+    plan.hops.drain_filter(|hop| hop.turn == state.turn)
+        .map(|hop| Move {
+            origin: state.planet_names[hop.origin].clone(),
+            destination: state.planet_names[hop.destination].clone(),
+            ship_count: hop.ship_count,
+        })
+        .collect()
+}
+
+impl Default for Flow1Algorithm {
+    fn default() -> Self {
+        Flow1Algorithm::new(FlowConfig::default())
+    }
+}
+
+// Enemy expeditions on their way to one of our planets are demand the flow has to meet: the
+// planet's node at the arrival step gets an edge to the sink for one ship more than the
+// enemies, as a tie leaves the planet neutral. The garrison, its growth and reinforcements
+// from other planets can all fill it, the ships it does not take are free to move. Returns the
+// demand by planet and time step, arrivals beyond the look ahead count at its last step.
+fn defence_demands(state: &State, config: &FlowConfig) -> BTreeMap<(i32, i32), i64> {
+    let mut demands = BTreeMap::new();
+    for ((turns_ahead, planet_id), ship_count) in state.hostile_arrivals() {
+        if state.predict_planet(0, planet_id).0 != state.own_id() {
+            continue;
+        }
+        let time_step = config.time_step_at(turns_ahead as i32).unwrap_or(config.look_ahead as i32);
+        *demands.entry((planet_id as i32, time_step)).or_insert(1) += ship_count;
+    }
+    demands
+}
+
+// planets side by side, turns from top to bottom, the out node of a planet right below its in node
+fn node_position(vertex: &Vertex<(i32, i32, i32)>) -> (f32, f32) {
+    let scale_factor = 200.0;
+    match vertex {
+        Vertex::Source => (-300.0, 1500.0),
+        Vertex::Sink => (2000.0, 1500.0),
+        Vertex::Node((planet_id, turns_ahead, in_out)) => {
+            let offset = *in_out as f32 * 0.5;
+            ((*planet_id as f32 + offset) * scale_factor, (*turns_ahead as f32 + offset) * scale_factor)
+        },
+    }
+}
+
+impl Strategy for Flow1Algorithm {
+    fn name(&self) -> &'static str {
+        "flow"
+    }
+
+    fn calculate(&mut self, state: &mut State, deadline: &Deadline) -> Vec<Move> {
+        self.last_graph = None;
+        if let Some(moves) = self.follow_plan(state) {
+            debug!("following the plan, move count: {}", moves.len());
+            return moves;
+        }
+        let Some(config) = self.budgeted_config(state, deadline.remaining()) else {
+            warn!("no time to solve a flow graph, using fallback");
+            return self.fallback.calculate(state, &deadline.hard_only());
+        };
+        if config.look_ahead < self.config.look_ahead {
+            debug!("look ahead cut to {} to finish in time", config.look_ahead);
+        }
+        let look_ahead = config.look_ahead as i32;
+        let time_steps = config.time_steps();
         // let mut cost_edges: BTreeSet<_> = BTreeSet::new();
         let mut graph_builder = GraphBuilder::new();
+        // nodes beyond the look ahead that already have their edges to the sink
+        let mut beyond_horizon = BTreeSet::new();
+        // the most negative cost of a capture edge so far
+        let mut best_capture_cost = 0;
 
         for origin_planet_id in 0..state.planet_names.len() {
-            for turns_ahead in 0..LOOK_AHEAD+1 {
-                let origin_planet_id = origin_planet_id as i32;
-                let turns_ahead = turns_ahead as i32;
+            if deadline.expired() {
+                // the solver can not be interrupted, so there is no use in starting it
+                warn!("flow graph not finished in time, using fallback");
+                return self.fallback.calculate(state, &deadline.hard_only());
+            }
+            let origin_planet_id = origin_planet_id as i32;
+            let mut previous_turns_ahead = None;
+            for &turns_ahead in &time_steps {
                 let origin_planet_node_in = (origin_planet_id, turns_ahead, 0);
                 let origin_planet_node_out = (origin_planet_id, turns_ahead, 1);
-                // let time_index = state.turn + turns_ahead;
                 let (owner, fleet_size) = state.predict_planet(turns_ahead as i64, origin_planet_id as PlanetId);
 
-                if turns_ahead != 0 {
+                if let Some(previous_turns_ahead) = previous_turns_ahead {
                     // if fleet isn't moved
-                    graph_builder.add_edge((origin_planet_id, turns_ahead-1, 1), origin_planet_node_in, Capacity(i32::MAX), Cost(0)); //TODO: play with stagnancy cost
+                    graph_builder.add_edge((origin_planet_id, previous_turns_ahead, 1), origin_planet_node_in, Capacity(i32::MAX), Cost(0)); //TODO: play with stagnancy cost
                 }
-                if owner == self.id {
-
-                    if turns_ahead != 0 {
-                        // graph_builder.add_edge((origin_planet_id, turns_ahead-1, 1), origin_planet_node_in, Capacity(1), Cost(-1000));
-                    }
+                if owner == state.own_id() {
                     graph_builder.add_edge(origin_planet_node_in, origin_planet_node_out, Capacity(i32::MAX), Cost(0)); // TODO: cost based on score/priority
 
-                    if turns_ahead == 0 {
+                    match previous_turns_ahead {
                         // starting fleet size
-                        graph_builder.add_edge(Vertex::Source, origin_planet_node_in, Capacity(fleet_size as i32), Cost(0)); 
-                    } else {
-                        let (last_owner, _last_fleet_size) = state.predict_planet(turns_ahead as i64 - 1, origin_planet_id as PlanetId);
-                        if last_owner == owner {
-                            // growth on owned planet
-                            graph_builder.add_edge(Vertex::Source, origin_planet_node_in, Capacity(1), Cost(0));
-                        } else {
-                            // allied expedition arrives
+                        None => {
                             graph_builder.add_edge(Vertex::Source, origin_planet_node_in, Capacity(fleet_size as i32), Cost(0));
-                            // TODO: edge (with negative cost, to that it is always taken) straight to Sink if an enemy expedition arrives
-                        }
+                        },
+                        Some(previous_turns_ahead) => {
+                            let (last_owner, _last_fleet_size) = state.predict_planet(previous_turns_ahead as i64, origin_planet_id as PlanetId);
+                            if last_owner == owner {
+                                // growth on owned planet, one ship for every turn since the previous node
+                                let growth = turns_ahead - previous_turns_ahead;
+                                graph_builder.add_edge(Vertex::Source, origin_planet_node_in, Capacity(growth), Cost(0));
+                            } else {
+                                // allied expedition arrives
+                                graph_builder.add_edge(Vertex::Source, origin_planet_node_in, Capacity(fleet_size as i32), Cost(0));
+                            }
+                        },
                     }
 
-                    if turns_ahead == LOOK_AHEAD as i32 {
+                    if turns_ahead == look_ahead {
                         // last nodes need an outflow
-                        graph_builder.add_edge(origin_planet_node_out, Vertex::Sink, Capacity(i32::MAX), Cost(IDLE_PENALTY_COST));
+                        graph_builder.add_edge(origin_planet_node_out, Vertex::Sink, Capacity(i32::MAX), Cost(self.config.idle_penalty_cost));
                     }
                 } else {
-                    // TODO: negative cost based on score/priority
                     let score = self.calculate_score(origin_planet_id as usize, turns_ahead as i64, state);
-                    graph_builder.add_edge(origin_planet_node_in, origin_planet_node_out, Capacity(fleet_size as i32 + 1), Cost(score as i32 * 1000));
-                    graph_builder.add_edge(origin_planet_node_in, origin_planet_node_out, Capacity(i32::MAX), Cost(0)); 
+                    let cost = self.add_capture_edges(&mut graph_builder, origin_planet_node_in, origin_planet_node_out, fleet_size, score);
+                    best_capture_cost = best_capture_cost.min(cost);
 
-                    if turns_ahead == LOOK_AHEAD as i32 {
+                    if turns_ahead == look_ahead {
                         // last nodes need an outflow
                         graph_builder.add_edge(
                             origin_planet_node_out, 
@@ -157,77 +379,130 @@ impl Flow1Algorithm {
                 }
 
                 // ================================ outgoing connections ==========================
-                for (distance, destination_planet_id) in &state.nearest_planets[origin_planet_id as usize] {
-                    // TODO: filter planets where turns_ahead + time_delta is past max turns
+                for (distance, destination_planet_id) in state.nearest_planets[origin_planet_id as usize].clone() {
                     let time_delta = distance.ceil() as i32;
                     let new_turns_ahead = turns_ahead + time_delta;
+                    // ships that arrive after the end of the game are of no use
+                    if state.turn + new_turns_ahead as i64 > state.max_turns {
+                        continue;
+                    }
+                    let destination_planet_id = destination_planet_id as i32;
+
+                    let Some(arrival_turns_ahead) = config.time_step_at(new_turns_ahead) else {
+                        // beyond the look ahead the destination is taken in a single step to the
+                        // sink, without any further moves
+                        let node_in = (destination_planet_id, new_turns_ahead, 0);
+                        let node_out = (destination_planet_id, new_turns_ahead, 1);
+                        graph_builder.add_edge(origin_planet_node_out, node_in, Capacity(i32::MAX), Cost(time_delta));
+                        if beyond_horizon.insert(node_in) {
+                            let (_, fleet_size) = state.predict_planet(new_turns_ahead as i64, destination_planet_id as PlanetId);
+                            let score = self.calculate_score(destination_planet_id as usize, new_turns_ahead as i64, state);
+                            let cost = self.add_capture_edges(&mut graph_builder, node_in, node_out, fleet_size, score * self.config.beyond_horizon_weight);
+                            best_capture_cost = best_capture_cost.min(cost);
+                            graph_builder.add_edge(node_out, Vertex::Sink, Capacity(i32::MAX), Cost(0));
+                        }
+                        continue;
+                    };
                     graph_builder.add_edge(
                         origin_planet_node_out,
-                        (*destination_planet_id as i32, new_turns_ahead, 0), 
+                        (destination_planet_id, arrival_turns_ahead, 0), 
                         Capacity(i32::MAX), 
                         Cost(time_delta)
                     );
+                }
+                previous_turns_ahead = Some(turns_ahead);
+            }
+        }
 
-                    if new_turns_ahead > LOOK_AHEAD as i32 { 
-
-                        graph_builder.add_edge(
-                            (*destination_planet_id as i32, new_turns_ahead, 0), 
-                            (*destination_planet_id as i32, new_turns_ahead, 1), 
-                            Capacity(i32::MAX), 
-                            Cost(-400) // TODO: negative cost based on score/priority
-                        );
+        // A ship passes at most one capture edge per time step, so defending is worth more than
+        // any route of captures
+        let defence_cost = best_capture_cost.saturating_mul(time_steps.len() as i32).saturating_sub(1);
+        for ((planet_id, time_step), ship_count) in defence_demands(state, &config) {
+            graph_builder.add_edge((planet_id, time_step, 0), Vertex::Sink, Capacity(ship_count as i32), Cost(defence_cost));
+        }
 
-                        graph_builder.add_edge(
-                            (*destination_planet_id as i32, new_turns_ahead, 1), 
-                            Vertex::Sink,
-                            Capacity(i32::MAX), 
-                            Cost(0) // TODO: negative cost based on score/priority
-                        );
-                         
-                    }
-                }
+        // building took longer than expected
+        if graph_builder.edge_list.len() as f64 > self.solvable_edges(deadline.remaining()) {
+            warn!("no time left to solve the flow graph, using fallback");
+            return self.fallback.calculate(state, &deadline.hard_only());
+        }
+        let solve_start = Instant::now();
+        let (cost, paths) = graph_builder.mcmf();
+        // very fast solves say little about larger graphs, so they count as a millisecond
+        let solve_ms = (solve_start.elapsed().as_secs_f64() * 1000.0).max(1.0);
+        self.edges_per_ms = graph_builder.edge_list.len() as f64 / solve_ms;
+        if let Some(options) = self.graph_export.as_ref().filter(|options| options.wants(state.turn)) {
+            let graph = ExportGraph::from_flow_graph(&graph_builder.edge_list, &paths, node_position);
+            write_graph_or_warn(options, &graph, &format!("turn-{:04}-flow", state.turn));
+        }
+        let nodes = graph_builder.edge_list.iter()
+            .flat_map(|(begin, end, _, _)| [begin, end])
+            .collect::<BTreeSet<_>>();
+        self.last_graph = Some(GraphMetrics {
+            nodes: nodes.len(),
+            edges: graph_builder.edge_list.len(),
+            cost: cost as i64,
+        });
+        debug!("cost: {cost}, paths: {}", paths.len());
+        if log_enabled!(Level::Trace) {
+            for path in &paths {
+                trace!("path cost: {}, flow: {}, vertices: {:?}", path.cost(), path.amount(), path.vertices());
             }
         }
-        
-        // write_graph_to_file(&graph_builder);
-        // exit(0);
-
-        let (_cost, paths) = graph_builder.mcmf();
-        eprintln!("COST: {_cost}");
-        let moves: Vec<_> = paths.iter()
-            // .map(|path| {
-            //     eprintln!("PATH START == COST: {}, FLOW: {}", path.cost(), path.amount());
-            //     for vertex in path.vertices() {
-            //         eprintln!("\t{vertex:?}");
-            //     }
-            //     eprintln!("PATH END");
-            //     path
-            // })
-            .flat_map(|path| path.edges()) //TODO: take the first 3 or 4 or so
-            .filter(|edge| {
-                matches!((edge.a, edge.b), (Vertex::Node((origin_planet_id, 0, 1)), Vertex::Node((destination_planet_id, _, _))) if origin_planet_id != destination_planet_id)
-            })
-            .map(|edge| {
-                let Vertex::Node((origin_planet_id, _, _)) = edge.a else {
-                    panic!("Move start node has unexpected value");
-                }; 
-                let Vertex::Node((destination_planet_id, _, _)) = edge.b else {
-                    panic!("Move end node has unexpected value");
-                };
-                Move {
-                    origin: state.planet_names[origin_planet_id as usize].clone(),
-                    destination: state.planet_names[destination_planet_id as usize].clone(),
-                    ship_count: edge.amount.try_into().unwrap()
+        // every travel edge of the solution is a hop, the ones of this turn are sent now
+        let mut hops = BTreeMap::new();
+        for edge in paths.iter().flat_map(|path| path.edges()) {
+            if let (Vertex::Node((origin_planet_id, turns_ahead, 1)), Vertex::Node((destination_planet_id, _, 0))) = (edge.a, edge.b) {
+                if origin_planet_id != destination_planet_id {
+                    let key = (state.turn + turns_ahead as i64, origin_planet_id as PlanetId, destination_planet_id as PlanetId);
+                    *hops.entry(key).or_insert(0) += i64::from(edge.amount);
                 }
-            }).collect();
+            }
+        }
+        let mut plan = FlowPlan {
+            solved_turn: state.turn,
+            hops: hops.into_iter()
+                .map(|((turn, origin, destination), ship_count)| PlannedHop { turn, origin, destination, ship_count })
+                .collect(),
+            hostile_arrivals: hostile_arrivals_by_turn(state),
+        };
+        let moves = take_due_moves(&mut plan, state);
+        debug!("planned {} later hops", plan.hops.len());
+        self.plan = Some(plan);
 
-        eprintln!("MOVE COUNT: {}", moves.len());
+        debug!("move count: {}", moves.len());
         moves
     }
 
+    fn report_metrics(&self, metrics: &mut TurnMetrics) {
+        metrics.graph = self.last_graph;
+    }
+
+    fn enable_graph_export(&mut self, options: GraphExportOptions) {
+        self.graph_export = Some(options);
+    }
+}
+
+impl Flow1Algorithm {
+    // Taking a planet that is not ours: the ships needed to take it are worth its score, any
+    // more ships pass without cost. Returns the cost of the capture.
+    fn add_capture_edges(
+        &self,
+        graph_builder: &mut GraphBuilder<(i32, i32, i32)>,
+        node_in: (i32, i32, i32),
+        node_out: (i32, i32, i32),
+        fleet_size: i64,
+        score: f32,
+    ) -> i32 {
+        let cost = -(score * self.config.score_scale).round() as i32;
+        graph_builder.add_edge(node_in, node_out, Capacity(fleet_size as i32 + 1), Cost(cost));
+        graph_builder.add_edge(node_in, node_out, Capacity(i32::MAX), Cost(0));
+        cost
+    }
+
     fn calculate_score(&self, planet_id: usize, turns_ahead: i64, state: &mut State) -> f32 {
         let (owner, fleet_size) = state.predict_planet(turns_ahead, planet_id);
-        if owner == self.id { 
+        if owner == state.own_id() { 
             return -3.0;
         }
         let nearest: SmallVec<[_; 3]> = state.nearest_planets[planet_id]
@@ -236,14 +511,223 @@ impl Flow1Algorithm {
                 let (owner, fleet_size) = state.predict_planet(turns_ahead, *other_planet_id);
                 (distance, other_planet_id, owner, fleet_size)
             }) 
-            .filter(|(_, _, owner, _)| *owner == self.id)
+            .filter(|(_, _, owner, _)| *owner == state.own_id())
             .take(1)
             .collect();
         if nearest.is_empty() {
             return 0.0;
         }
-        let (_distance, _, _, other_fleet_size) = nearest[0];
-        (other_fleet_size - fleet_size) as f32 / _distance
+        let (distance, _, _, other_fleet_size) = nearest[0];
+        (other_fleet_size - fleet_size) as f32 / distance
         // -fleet_size as f32 + SCORE_OFFSET
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::{Duration, Instant}};
+
+    use super::{Flow1Algorithm, FlowConfig, FlowPlan, PlannedHop};
+    use crate::{MAX_TURNS, deadline::Deadline, state::State, strategy::Strategy, structs::{Expedition, Move}, test_util::input};
+
+    // ships from a take b, and then continue to c
+    fn algorithm_with_plan() -> Flow1Algorithm {
+        let mut algorithm = Flow1Algorithm::new(FlowConfig::default());
+        algorithm.plan = Some(FlowPlan {
+            solved_turn: 0,
+            hops: vec![
+                PlannedHop { turn: 0, origin: 0, destination: 1, ship_count: 8 },
+                PlannedHop { turn: 10, origin: 1, destination: 2, ship_count: 2 },
+            ],
+            hostile_arrivals: BTreeMap::new(),
+        });
+        algorithm
+    }
+
+    #[test]
+    fn plan_is_followed_across_turns() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", None, 1)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = algorithm_with_plan();
+        let hop = PlannedHop { turn: 1, origin: 0, destination: 2, ship_count: 2 };
+        algorithm.plan.as_mut().unwrap().hops.insert(1, hop);
+        let moves = algorithm.follow_plan(&state).unwrap();
+        assert_eq!(moves, vec![Move { origin: "a".to_string(), destination: "b".to_string(), ship_count: 8 }]);
+
+        state.tick();
+        let expedition = Expedition {
+            id: 0,
+            ship_count: 8,
+            origin: "a".to_string(),
+            destination: "b".to_string(),
+            owner: 1,
+            turns_remaining: 9,
+        };
+        state.update(input(&[("a", Some(1), 3), ("b", None, 5), ("c", None, 1)], vec![expedition])).unwrap();
+        let moves = algorithm.follow_plan(&state).unwrap();
+        assert_eq!(moves, vec![Move { origin: "a".to_string(), destination: "c".to_string(), ship_count: 2 }]);
+        assert_eq!(algorithm.plan.as_ref().map(|plan| plan.hops.len()), Some(1));
+    }
+
+    #[test]
+    fn turns_without_a_hop_are_solved_again() {
+        // the game ends when ships that leave now reach b, later ones are too late
+        let mut state = State::new(input(&[("a", Some(1), 30), ("b", None, 5), ("c", None, 1)], vec![]), 1, 10);
+        let mut algorithm = algorithm_with_plan();
+        // a single ship of a is planned to leave later, the rest is left out
+        algorithm.plan.as_mut().unwrap().hops = vec![PlannedHop { turn: 5, origin: 0, destination: 2, ship_count: 1 }];
+        let moves = algorithm.calculate(&mut state, &Deadline::unlimited());
+        assert!(moves.iter().any(|planned_move| planned_move.destination == "b"), "moves {moves:?}");
+        assert!(algorithm.last_graph.is_some());
+    }
+
+    #[test]
+    fn plan_is_dropped_when_ships_are_missing() {
+        let state = State::new(input(&[("a", Some(1), 7), ("b", None, 5), ("c", None, 1)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = algorithm_with_plan();
+        assert_eq!(algorithm.follow_plan(&state), None);
+        assert!(algorithm.plan.is_none());
+    }
+
+    #[test]
+    fn plan_is_dropped_when_enemies_approach_its_planets() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", None, 1)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = algorithm_with_plan();
+        algorithm.plan.as_mut().unwrap().hops.remove(0);
+        let enemies = |destination: &str, id| Expedition {
+            id,
+            ship_count: 4,
+            origin: "c".to_string(),
+            destination: destination.to_string(),
+            owner: 2,
+            turns_remaining: 3,
+        };
+        // the remaining hop goes from b to c, a does not matter to it
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", Some(1), 5), ("c", None, 1)], vec![enemies("a", 0)])).unwrap();
+        assert_eq!(algorithm.plan.as_ref().unwrap().validate(&state), Ok(()));
+
+        state.tick();
+        let expeditions = vec![enemies("a", 0), enemies("b", 1)];
+        state.update(input(&[("a", Some(1), 12), ("b", Some(1), 6), ("c", None, 1)], expeditions)).unwrap();
+        assert!(algorithm.plan.as_ref().unwrap().validate(&state).is_err());
+    }
+
+    #[test]
+    fn defenders_stay_for_a_valuable_capture() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 0), ("c", Some(2), 1)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        let expedition = Expedition {
+            id: 0,
+            ship_count: 8,
+            origin: "c".to_string(),
+            destination: "a".to_string(),
+            owner: 2,
+            turns_remaining: 5,
+        };
+        state.update(input(&[("a", Some(1), 11), ("b", None, 0), ("c", Some(2), 2)], vec![expedition])).unwrap();
+        // capturing b is worth far more per ship than anything else in the graph
+        let mut algorithm = Flow1Algorithm::new(FlowConfig { score_scale: 100_000.0, ..FlowConfig::default() });
+        let moves = algorithm.calculate(&mut state, &Deadline::unlimited());
+
+        // a has 16 ships when the 8 enemies arrive, 7 can leave now and one more has to survive
+        let sent: i64 = moves.iter()
+            .filter(|planned_move| planned_move.origin == "a")
+            .map(|planned_move| planned_move.ship_count)
+            .sum();
+        assert!(sent > 0 && sent <= 7, "sent {sent}");
+        for planned_move in &moves {
+            state.plan_move(planned_move).unwrap();
+        }
+        assert_eq!(state.predict_planet(5, 0).0, Some(1));
+    }
+
+    #[test]
+    fn graph_is_cut_to_the_remaining_time() {
+        let state = State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", None, 1)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = Flow1Algorithm::new(FlowConfig::default());
+        // 100 edges fit into half of 200ms, a turn ahead needs up to 33
+        algorithm.edges_per_ms = 1.0;
+        let look_ahead = |remaining| algorithm.budgeted_config(&state, remaining).map(|config| config.look_ahead);
+        assert_eq!(look_ahead(Duration::from_millis(200)), Some(2));
+        assert_eq!(look_ahead(Duration::from_secs(60)), Some(40));
+        assert_eq!(look_ahead(Duration::from_millis(100)), None);
+    }
+
+    #[test]
+    fn edge_estimate_is_an_upper_bound() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", Some(2), 1)], vec![]), 1, MAX_TURNS);
+        for look_ahead in [1, 5, 40] {
+            let config = FlowConfig { look_ahead, fine_turns: 10, coarse_step: 3, ..FlowConfig::default() };
+            let mut algorithm = Flow1Algorithm::new(config.clone());
+            algorithm.calculate(&mut state, &Deadline::unlimited());
+            let edges = algorithm.last_graph.unwrap().edges;
+            assert!(edges <= config.estimated_edges(&state), "{edges} edges with a look ahead of {look_ahead}");
+        }
+    }
+
+    #[test]
+    fn fallback_plays_when_no_graph_fits() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", None, 1)], vec![]), 1, MAX_TURNS);
+        let mut algorithm = Flow1Algorithm::new(FlowConfig::default());
+        algorithm.edges_per_ms = 0.0;
+        algorithm.calculate(&mut state, &Deadline::for_turn(Instant::now()));
+        assert!(algorithm.last_graph.is_none());
+        assert!(algorithm.plan.is_none());
+    }
+
+    #[test]
+    fn time_steps_turn_coarse_after_the_fine_turns() {
+        let config = FlowConfig { look_ahead: 20, fine_turns: 10, coarse_step: 4, ..FlowConfig::default() };
+        let time_steps = config.time_steps();
+        assert_eq!(time_steps, [(0..=10).collect(), vec![14, 18, 20]].concat());
+        // arrivals wait for the next time step, the last one is cut short by the look ahead
+        let steps = [10, 11, 14, 15, 19, 20].map(|turns_ahead| config.time_step_at(turns_ahead));
+        assert_eq!(steps, [10, 14, 14, 18, 20, 20].map(Some));
+        assert_eq!(config.time_step_at(21), None);
+        for turns_ahead in 0..=20 {
+            assert!(time_steps.contains(&config.time_step_at(turns_ahead).unwrap()));
+        }
+    }
+
+    #[test]
+    fn time_steps_end_at_the_look_ahead() {
+        let config = FlowConfig { look_ahead: 18, fine_turns: 10, coarse_step: 4, ..FlowConfig::default() };
+        assert_eq!(config.time_steps(), [(0..=10).collect(), vec![14, 18]].concat());
+        assert_eq!(config.time_step_at(15), Some(18));
+
+        // the look ahead within the fine turns has no coarse steps
+        let config = FlowConfig { look_ahead: 5, fine_turns: 10, coarse_step: 4, ..FlowConfig::default() };
+        assert_eq!(config.time_steps(), (0..=5).collect::<Vec<_>>());
+        assert_eq!(config.time_step_at(5), Some(5));
+        assert_eq!(config.time_step_at(6), None);
+    }
+
+    #[test]
+    fn neighbours_reinforce_a_planet_that_would_fall() {
+        let mut state = State::new(input(&[("a", Some(1), 5), ("b", Some(1), 30), ("c", Some(2), 1)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        let expedition = Expedition {
+            id: 0,
+            ship_count: 20,
+            origin: "c".to_string(),
+            destination: "a".to_string(),
+            owner: 2,
+            turns_remaining: 10,
+        };
+        state.update(input(&[("a", Some(1), 5), ("b", Some(1), 30), ("c", Some(2), 2)], vec![expedition])).unwrap();
+        // a has 15 ships when the enemies arrive, only ships that leave b now are in time
+        assert_eq!(state.predict_planet(10, 0).0, Some(2));
+        let mut algorithm = Flow1Algorithm::new(FlowConfig::default());
+        let moves = algorithm.calculate(&mut state, &Deadline::unlimited());
+
+        let reinforcements: i64 = moves.iter()
+            .filter(|planned_move| planned_move.origin == "b" && planned_move.destination == "a")
+            .map(|planned_move| planned_move.ship_count)
+            .sum();
+        assert!(reinforcements >= 6, "sent {reinforcements}");
+        for planned_move in &moves {
+            state.plan_move(planned_move).unwrap();
+        }
+        assert_eq!(state.predict_planet(10, 0).0, Some(1));
+    }
+}
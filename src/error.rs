@@ -0,0 +1,70 @@
+use std::{fmt, io};
+
+use crate::structs::PlanetName;
+
+#[derive(Debug)]
+pub enum StateError {
+    UnknownPlanet(PlanetName),
+    PlanetCountMismatch { expected: usize, received: usize },
+    TurnOutOfRange(i64),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::UnknownPlanet(name) => write!(f, "unknown planet '{name}'"),
+            StateError::PlanetCountMismatch { expected, received } => {
+                write!(f, "expected {expected} planets, received {received}")
+            },
+            StateError::TurnOutOfRange(turn) => write!(f, "turn {turn} is outside of the stored state"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// everything that can go wrong while playing a single turn
+#[derive(Debug)]
+pub enum BotError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    State(StateError),
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::Io(err) => write!(f, "I/O error: {err}"),
+            BotError::Parse(err) => write!(f, "invalid JSON: {err}"),
+            BotError::State(err) => write!(f, "invalid game state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotError::Io(err) => Some(err),
+            BotError::Parse(err) => Some(err),
+            BotError::State(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for BotError {
+    fn from(err: io::Error) -> Self {
+        BotError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(err: serde_json::Error) -> Self {
+        BotError::Parse(err)
+    }
+}
+
+impl From<StateError> for BotError {
+    fn from(err: StateError) -> Self {
+        BotError::State(err)
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, fmt::{Debug, Write as _}, fs, io, ops::RangeInclusive, path::PathBuf};
+
+use log::warn;
+use mcmf::{Capacity, Cost, Path as FlowPath, Vertex};
+use serde_json::json;
+
+use crate::{config::ConfigError, state::State, structs::Move};
+
+// vis.js page that shows the exported JSON files
+const VIEWER: &str = include_str!("graph_viewer.html");
+
+// Where graphs are exported to and for which turns, set with `--export-graphs` and
+// `--export-turns`
+#[derive(Debug, Clone)]
+pub struct GraphExportOptions {
+    pub dir: PathBuf,
+    // every turn when empty
+    pub turns: Vec<RangeInclusive<i64>>,
+}
+
+impl GraphExportOptions {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        GraphExportOptions { dir: dir.into(), turns: Vec::new() }
+    }
+
+    // a comma separated list of turns and ranges of turns, like `10,20-25`
+    pub fn parse_turns(spec: &str) -> Result<Vec<RangeInclusive<i64>>, ConfigError> {
+        spec.split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let invalid = || ConfigError::InvalidArgument(format!("turns '{part}'"));
+                let (first, last) = part.split_once('-').unwrap_or((part, part));
+                let first = first.trim().parse().map_err(|_| invalid())?;
+                let last = last.trim().parse().map_err(|_| invalid())?;
+                Ok(first..=last)
+            })
+            .collect()
+    }
+
+    pub fn wants(&self, turn: i64) -> bool {
+        self.turns.is_empty() || self.turns.iter().any(|turns| turns.contains(&turn))
+    }
+
+    // Writes the graph as `<name>.dot` and `<name>.json` into the export directory, together
+    // with a viewer for the JSON files: `viewer.html?graph=<name>`
+    pub fn write(&self, graph: &ExportGraph, name: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let viewer = self.dir.join("viewer.html");
+        if !viewer.exists() {
+            fs::write(viewer, VIEWER)?;
+        }
+        fs::write(self.dir.join(format!("{name}.dot")), graph.to_dot())?;
+        fs::write(self.dir.join(format!("{name}.json")), serde_json::to_string_pretty(&graph.to_vis_json())?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportNode {
+    pub label: String,
+    // fixed layout position, left to the viewer otherwise
+    pub position: Option<(f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportEdge {
+    pub from: usize,
+    pub to: usize,
+    pub label: String,
+    // ships the solution sends along the edge
+    pub flow: i64,
+}
+
+// A graph in a form that can be written for Graphviz and vis.js. Nodes are referred to by
+// their index.
+#[derive(Debug, Clone, Default)]
+pub struct ExportGraph {
+    pub nodes: Vec<ExportNode>,
+    pub edges: Vec<ExportEdge>,
+}
+
+impl ExportGraph {
+    pub fn add_node(&mut self, label: impl Into<String>, position: Option<(f32, f32)>) -> usize {
+        self.nodes.push(ExportNode { label: label.into(), position });
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: usize, to: usize, label: impl Into<String>, flow: i64) {
+        self.edges.push(ExportEdge { from, to, label: label.into(), flow });
+    }
+
+    // A min cost flow graph and its solution. Parallel edges are told apart by their cost,
+    // `position` lays out the nodes.
+    pub fn from_flow_graph<T: Clone + Ord + Debug>(
+        edge_list: &[(Vertex<T>, Vertex<T>, Capacity, Cost)],
+        paths: &[FlowPath<T>],
+        position: impl Fn(&Vertex<T>) -> (f32, f32),
+    ) -> Self {
+        let mut flows = BTreeMap::new();
+        for flow in paths.iter().flat_map(|path| path.edges()) {
+            *flows.entry((&flow.a, &flow.b, flow.cost)).or_insert(0) += i64::from(flow.amount);
+        }
+
+        let mut graph = ExportGraph::default();
+        let mut node_ids = BTreeMap::new();
+        for (begin, end, Capacity(capacity), Cost(cost)) in edge_list {
+            let [from, to] = [begin, end].map(|vertex| {
+                *node_ids.entry(vertex).or_insert_with(|| {
+                    let label = match vertex {
+                        Vertex::Node(node) => format!("{node:?}"),
+                        other => format!("{other:?}"),
+                    };
+                    graph.add_node(label, Some(position(vertex)))
+                })
+            });
+            let capacity_label = if *capacity == i32::MAX { "∞".to_string() } else { capacity.to_string() };
+            // every unit of flow is taken from the first matching edge
+            let flow = flows.get_mut(&(begin, end, *cost))
+                .map_or(0, |flow| {
+                    let taken = (*flow).min(i64::from(*capacity).max(0));
+                    *flow -= taken;
+                    taken
+                });
+            graph.add_edge(from, to, format!("cap {capacity_label}, cost {cost}"), flow);
+        }
+        graph
+    }
+
+    // The planets at their location, with an edge to their nearest neighbours and the moves
+    // of the turn as flows
+    pub fn from_planets(state: &State, moves: &[Move]) -> Self {
+        let mut graph = ExportGraph::default();
+        for planet in &state.current_state.planets {
+            let owner = planet.owner.map_or("neutral".to_string(), |owner| format!("player {owner}"));
+            graph.add_node(format!("{}\n{owner}\n{}", planet.name, planet.ship_count), Some((planet.x, planet.y)));
+        }
+        let mut sent = BTreeMap::new();
+        for planned_move in moves {
+            let key = (state.planet_map[&planned_move.origin], state.planet_map[&planned_move.destination]);
+            *sent.entry(key).or_insert(0) += planned_move.ship_count;
+        }
+        for (origin, nearest) in state.nearest_planets.iter().enumerate() {
+            for &(distance, destination) in nearest {
+                let flow = sent.remove(&(origin, destination)).unwrap_or(0);
+                graph.add_edge(origin, destination, format!("{distance:.1}"), flow);
+            }
+        }
+        // moves to planets that are not among the nearest
+        for ((origin, destination), flow) in sent {
+            graph.add_edge(origin, destination, format!("{:.1}", state.distance(origin, destination)), flow);
+        }
+        graph
+    }
+
+    // Graphviz, positions are only used by layouts like `neato -n`
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            write!(dot, "  n{id} [label=\"{}\"", escape(&node.label)).unwrap();
+            if let Some((x, y)) = node.position {
+                write!(dot, ", pos=\"{x},{y}!\"").unwrap();
+            }
+            dot.push_str("];\n");
+        }
+        for edge in &self.edges {
+            write!(dot, "  n{} -> n{} [label=\"{}", edge.from, edge.to, escape(&edge.label)).unwrap();
+            if edge.flow > 0 {
+                write!(dot, "\\nflow {}\", color=red, penwidth=2", edge.flow).unwrap();
+            } else {
+                dot.push('"');
+            }
+            dot.push_str("];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the `nodes` and `edges` of a vis.js network
+    pub fn to_vis_json(&self) -> serde_json::Value {
+        let nodes = self.nodes.iter()
+            .enumerate()
+            .map(|(id, node)| {
+                let mut value = json!({ "id": id, "label": node.label });
+                if let Some((x, y)) = node.position {
+                    value["x"] = json!(x);
+                    value["y"] = json!(y);
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        let edges = self.edges.iter()
+            .map(|edge| {
+                let mut value = json!({
+                    "from": edge.from,
+                    "to": edge.to,
+                    "label": edge.label,
+                    "arrows": "to",
+                    "flow": edge.flow,
+                });
+                if edge.flow > 0 {
+                    value["label"] = json!(format!("{}\nflow {}", edge.label, edge.flow));
+                    value["color"] = json!({ "color": "red" });
+                    value["width"] = json!(2);
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        json!({ "nodes": nodes, "edges": edges })
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// exporting is a debugging aid, so failing to write does not stop the game
+pub fn write_graph_or_warn(options: &GraphExportOptions, graph: &ExportGraph, name: &str) {
+    if let Err(err) = options.write(graph, name) {
+        warn!("could not export graph {name} to {}: {err}", options.dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportGraph, GraphExportOptions};
+
+    #[test]
+    fn turns_are_parsed() {
+        let mut options = GraphExportOptions::new("graphs");
+        assert!(options.wants(7));
+        options.turns = GraphExportOptions::parse_turns("3, 10-12").unwrap();
+        assert!(options.wants(3) && options.wants(11));
+        assert!(!options.wants(4) && !options.wants(13));
+        assert!(GraphExportOptions::parse_turns("3-x").is_err());
+    }
+
+    #[test]
+    fn flows_are_marked() {
+        let mut graph = ExportGraph::default();
+        let a = graph.add_node("a \"1\"", Some((0.0, 1.0)));
+        let b = graph.add_node("b", None);
+        graph.add_edge(a, b, "cap 3", 2);
+        graph.add_edge(b, a, "cap 1", 0);
+        let dot = graph.to_dot();
+        assert!(dot.contains("n0 [label=\"a \\\"1\\\"\", pos=\"0,1!\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"cap 3\\nflow 2\", color=red, penwidth=2];"));
+        assert!(dot.contains("n1 -> n0 [label=\"cap 1\"];"));
+        let json = graph.to_vis_json();
+        assert_eq!(json["edges"][0]["flow"], 2);
+        assert_eq!(json["nodes"][1].get("x"), None);
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{sim::Player, structs::{Input, Move, Output}};
+
+pub const DEFAULT_TURN_TIMEOUT: Duration = Duration::from_millis(crate::HARD_MAX_DURATION);
+
+// An external bot, speaking the same line delimited JSON protocol as the server: it gets an
+// `Input` per line on stdin and has to answer with an `Output` on a single line of stdout.
+pub struct ProcessBot {
+    pub command: String,
+    pub turn_timeout: Duration,
+    child: Child,
+    stdin: ChildStdin,
+    // stdout is read on a separate thread so that waiting for an answer can time out
+    lines: Receiver<io::Result<String>>,
+}
+
+impl ProcessBot {
+    // the command is run through the shell, so it can be copied from a runner config
+    pub fn spawn(command: &str, turn_timeout: Duration) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ProcessBot { command: command.to_string(), turn_timeout, child, stdin, lines })
+    }
+
+    // The answer has to arrive before `deadline`, however many blank lines come first
+    fn read_output(&mut self, deadline: Instant) -> Result<Output, String> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(remaining) {
+                Ok(Ok(line)) => line,
+                Ok(Err(err)) => return Err(format!("could not read from bot: {err}")),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("no answer within {}ms", self.turn_timeout.as_millis()));
+                },
+                Err(RecvTimeoutError::Disconnected) => return Err("bot exited".to_string()),
+            };
+            // bots are allowed to send blank lines between turns
+            if line.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str(&line).map_err(|err| format!("invalid output {line:?}: {err}"));
+        }
+    }
+}
+
+impl Player for ProcessBot {
+    fn play_turn(&mut self, input: Input) -> Result<Vec<Move>, String> {
+        let line = serde_json::to_string(&input).map_err(|err| err.to_string())?;
+        let deadline = Instant::now() + self.turn_timeout;
+        writeln!(self.stdin, "{line}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| format!("could not write to bot: {err}"))?;
+        Ok(self.read_output(deadline)?.moves)
+    }
+
+    fn game_over(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for ProcessBot {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::ProcessBot;
+    use crate::{sim::Player, test_util::input};
+
+    #[test]
+    fn blank_lines_do_not_extend_the_turn() {
+        let mut bot = ProcessBot::spawn("while true; do echo; sleep 0.05; done", Duration::from_millis(300)).unwrap();
+        let start = Instant::now();
+        let result = bot.play_turn(input(&[("a", Some(1), 10)], vec![]));
+        assert_eq!(result, Err("no answer within 300ms".to_string()));
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn answers_after_blank_lines_are_read() {
+        let command = r#"read line; echo; echo '{"moves": []}'; sleep 5"#;
+        let mut bot = ProcessBot::spawn(command, Duration::from_secs(2)).unwrap();
+        assert_eq!(bot.play_turn(input(&[("a", Some(1), 10)], vec![])), Ok(vec![]));
+    }
+}
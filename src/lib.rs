@@ -0,0 +1,29 @@
+#![feature(drain_filter)]
+
+pub mod state;
+pub mod structs;
+pub mod simple_algoritm;
+pub mod direct_flow;
+pub mod neighbourhood;
+pub mod ants;
+pub mod strategy;
+pub mod config;
+pub mod sim;
+pub mod bot;
+pub mod harness;
+pub mod deadline;
+pub mod error;
+pub mod validate;
+pub mod combat;
+pub mod replay;
+pub mod logging;
+pub mod metrics;
+pub mod outcome;
+pub mod graph_export;
+#[cfg(test)]
+mod test_util;
+
+// turn limit of the server, used when no other one is given
+pub const MAX_TURNS: u64 = 500;
+pub const HARD_MAX_DURATION: u64 = 1000;
+pub const MAX_DURATION: u64 = 800;
@@ -0,0 +1,146 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path, sync::Mutex, time::{SystemTime, UNIX_EPOCH}};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use crate::config::ConfigError;
+
+pub const DEFAULT_LOG_SPEC: &str = "info";
+// targets of this crate are matched without it, so `state=debug` enables the state module
+const CRATE_PREFIX: &str = "enceladus_bot::";
+
+// Which levels are logged for which targets, parsed from a spec like
+// `info,state=debug,direct_flow=trace`. A directive without target sets the default level,
+// the most specific directive that matches a target wins.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> Result<Self, ConfigError> {
+        let mut filter = LogFilter { default: LevelFilter::Info, directives: Vec::new() };
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let invalid = || ConfigError::InvalidArgument(format!("log directive '{directive}'"));
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = level.parse().map_err(|_| invalid())?;
+                    filter.directives.push((target.trim().to_string(), level));
+                },
+                None => filter.default = directive.parse().map_err(|_| invalid())?,
+            }
+        }
+        // longest first, so the first match is the most specific one
+        filter.directives.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let target = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+        self.directives.iter()
+            .find(|(directive, _)| {
+                target == directive || target.strip_prefix(directive.as_str()).map_or(false, |rest| rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives.iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter::parse(DEFAULT_LOG_SPEC).expect("default spec is valid")
+    }
+}
+
+// a log line in the JSON log file
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time_ms: u128,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+// Writes readable lines to stderr, stdout is reserved for the moves. Optionally every record
+// is also written as a line of JSON to a file.
+struct Logger {
+    filter: LogFilter,
+    file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let target = record.target().strip_prefix(CRATE_PREFIX).unwrap_or(record.target());
+        eprintln!("[{} {target}] {}", record.level(), record.args());
+
+        if let Some(file) = &self.file {
+            let json_record = JsonRecord {
+                time_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis()),
+                level: record.level().as_str(),
+                target,
+                message: record.args().to_string(),
+            };
+            let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+            let written = serde_json::to_writer(&mut *file, &json_record)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(file))
+                .and_then(|_| file.flush());
+            if let Err(err) = written {
+                eprintln!("could not write log file: {err}");
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            file.lock().unwrap_or_else(|err| err.into_inner()).flush().ok();
+        }
+    }
+}
+
+// Installs the logger for the rest of the process, can only be called once.
+pub fn init(filter: LogFilter, file: Option<&Path>) -> Result<(), ConfigError> {
+    let file = file
+        .map(|path| File::create(path).map(|file| Mutex::new(BufWriter::new(file))))
+        .transpose()?;
+    log::set_max_level(filter.max_level());
+    log::set_boxed_logger(Box::new(Logger { filter, file }))
+        .map_err(|err| ConfigError::InvalidArgument(format!("logger: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LevelFilter;
+
+    use super::LogFilter;
+
+    #[test]
+    fn most_specific_directive_wins() {
+        let filter = LogFilter::parse("warn,state=debug,state::predictions=trace,io=off").unwrap();
+        assert_eq!(filter.level_for("enceladus_bot::direct_flow"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("enceladus_bot::state"), LevelFilter::Debug);
+        assert_eq!(filter.level_for("enceladus_bot::state::predictions"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("enceladus_bot::statement"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("io"), LevelFilter::Off);
+    }
+
+    #[test]
+    fn invalid_directives_are_rejected() {
+        assert!(LogFilter::parse("loud").is_err());
+        assert!(LogFilter::parse("state=loud").is_err());
+        assert_eq!(LogFilter::parse("").unwrap().level_for("state"), LevelFilter::Info);
+    }
+}
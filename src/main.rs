@@ -1,73 +1,197 @@
-#![feature(drain_filter)]
+use std::{io::{self, BufRead, Write}, time::Instant};
 
-use std::{io::{self, BufRead, Write}, collections::BTreeMap};
+use log::{debug, error, info, trace, warn};
+use serde_json::{Map, Value};
 
-use bit_set::BitSet;
-use neighbourhood::NeighbourhoodAlrorithm;
-use state::State;
-use structs::Input;
+use enceladus_bot::{
+    bot::Bot,
+    config::{self, Args, ConfigError},
+    deadline::Deadline,
+    error::BotError,
+    logging,
+    replay::{self, Replay, ReplayHeader, ReplayRecorder},
+    strategy::{STRATEGY_NAMES, Strategy, build_strategy},
+    structs::{Move, Output},
+};
 
-use crate::structs::Output;
+fn play_line(bot: &mut Bot, line: &str, deadline: Deadline, recorder: Option<&mut ReplayRecorder>) -> Result<Vec<Move>, BotError> {
+    let input = match bot.parse_input(line) {
+        Ok(input) => input,
+        Err(err) => {
+            record_skipped(&err, deadline, recorder);
+            return Err(err);
+        },
+    };
+    let Some(recorder) = recorder else {
+        return bot.play_turn(input, deadline);
+    };
+    let moves = bot.play_turn(input.clone(), deadline);
+    if let Err(err) = recorder.record(input, &moves, deadline.elapsed()) {
+        warn!(target: "io", "could not record turn: {err}");
+    }
+    moves
+}
 
+// The bot skipped a turn it could not read, the replay has to skip it as well
+fn record_skipped(err: &BotError, deadline: Deadline, recorder: Option<&mut ReplayRecorder>) {
+    if let Some(recorder) = recorder {
+        if let Err(err) = recorder.record_skipped(err, deadline.elapsed()) {
+            warn!(target: "io", "could not record turn: {err}");
+        }
+    }
+}
 
-mod state;
-mod structs;
-mod simple_algoritm;
-mod direct_flow;
-mod neighbourhood;
-mod ants;
+fn parse_args_or_exit(args: impl IntoIterator<Item = String>) -> Args {
+    match config::parse_args(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{}", config::USAGE);
+            std::process::exit(1);
+        }
+    }
+}
 
-const MAX_TURNS: u64 = 500;
-#[allow(dead_code)]
-const HARD_MAX_DURATION: u64 = 1000;
-#[allow(dead_code)]
-const MAX_DURATION: u64 = 800;
+fn init_logging_or_exit(args: &Args) {
+    if let Err(err) = logging::init(args.log.clone(), args.log_file.as_deref()) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
 
-fn main() {
+fn build_strategy_or_exit(strategy: &str, params: Map<String, Value>) -> Box<dyn Strategy> {
+    match build_strategy(strategy, params) {
+        Ok(algorithm) => algorithm,
+        Err(err @ ConfigError::UnknownStrategy(_)) => {
+            eprintln!("{err}, available strategies: {}", STRATEGY_NAMES.join(", "));
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
 
-    let stdin = io::stdin();
-    let mut state = State {
-        nearest_planets: vec![],
-        current_state: Input { planets: vec![], expeditions: vec![] },
-        planet_names: vec![],
-        state: vec![],
-        saved_expeditions: BitSet::new(),
-        planet_map: BTreeMap::new(),
-        turn: 0,
+fn format_moves(moves: &[Move]) -> String {
+    moves.iter()
+        .map(|planned_move| format!("{} -> {} ({})", planned_move.origin, planned_move.destination, planned_move.ship_count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Plays a recorded game again and prints the turns where the moves differ. The recorded
+// strategy and parameters are used unless the arguments override them. Exits with an error
+// when any turn differs.
+fn replay_game(mut args: impl Iterator<Item = String>) {
+    let Some(path) = args.next() else {
+        eprintln!("replay expects a file\n{}", config::USAGE);
+        std::process::exit(1);
+    };
+    let args = parse_args_or_exit(args);
+    init_logging_or_exit(&args);
+    let Replay { header, turns } = match replay::read_replay(&path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            eprintln!("could not read replay {path}: {err}");
+            std::process::exit(1);
+        }
     };
-    let mut algorithm = NeighbourhoodAlrorithm::default();
-    // let mut algorithm = SimpleAlrorithm {};
+    let (strategy, params) = header.resolve(args.strategy_chosen.then_some(args.strategy.as_str()), &args.params);
+    let algorithm = build_strategy_or_exit(&strategy, params);
+    info!(target: "io", "replaying {} turns recorded with {} using {}", turns.len(), header.strategy, algorithm.name());
 
+    let mut bot = Bot::new(algorithm, header.player_id, header.max_turns);
+    if let Some(options) = &args.graph_export {
+        bot.enable_graph_export(options.clone());
+    }
+    let diffs = replay::replay(&turns, &mut bot);
+    for diff in &diffs {
+        println!("turn {}", diff.turn);
+        println!("  recorded: {}", format_moves(&diff.recorded));
+        println!("  replayed: {}", format_moves(&diff.replayed));
+    }
+    println!("{} of {} turns differ", diffs.len(), turns.len());
+    if !diffs.is_empty() {
+        std::process::exit(1);
+    }
+}
 
-    for line in stdin.lock().lines() {
-        // let now = Instant::now();
-        let line = line.unwrap();
-        eprintln!("=========================================================");
-        // eprintln!("{}", line);
-        let input: Input = serde_json::from_str(&line).unwrap();
-        if state.turn == 0 {
-            state = State::new(input);
-        } else {
-            state.update(input);
-        }
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map_or(false, |arg| arg == "replay") {
+        args.next();
+        replay_game(args);
+        return;
+    }
 
-        // match state.check_gameover() {
-        //     GameSituation::WON => eprintln!("WE HAVE WON!"),
-        //     GameSituation::LOST => eprintln!("WE HAVE LOST!"),
-        //     _ => {}
-        // }
+    let args = parse_args_or_exit(args);
+    init_logging_or_exit(&args);
+    let algorithm = build_strategy_or_exit(&args.strategy, args.params.clone());
+    info!(target: "io", "using strategy: {}", algorithm.name());
+    let mut recorder = args.record.as_ref().map(|path| {
+        let header = ReplayHeader {
+            strategy: algorithm.name().to_string(),
+            player_id: args.player_id,
+            max_turns: args.max_turns,
+            params: args.params.clone(),
+        };
+        ReplayRecorder::create(path, &header).unwrap_or_else(|err| {
+            eprintln!("could not create replay {}: {err}", path.display());
+            std::process::exit(1);
+        })
+    });
 
+    let stdin = io::stdin();
+    let mut bot = Bot::new(algorithm, args.player_id, args.max_turns);
+    if let Some(options) = &args.graph_export {
+        bot.enable_graph_export(options.clone());
+    }
 
+    for line in stdin.lock().lines() {
+        let deadline = Deadline::for_turn(Instant::now());
+        let moves = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => {
+                debug!(target: "io", "received {} bytes", line.len());
+                trace!(target: "io", "{line}");
+                play_line(&mut bot, &line, deadline, recorder.as_mut())
+            },
+            // a line that is not valid UTF-8 still is a turn that needs an answer
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                bot.skip_turn();
+                let err = err.into();
+                record_skipped(&err, deadline, recorder.as_mut());
+                Err(err)
+            },
+            Err(err) => {
+                error!(target: "io", "could not read from stdin: {err}");
+                break;
+            },
+        };
         let output = Output {
-            moves: algorithm.calculate(&mut state)
+            moves: moves.unwrap_or_else(|err| {
+                error!(target: "io", "{err}, answering with no moves");
+                Vec::new()
+            })
         };
 
-        // while now.elapsed() < Duration::from_millis(MAX_DURATION) {
-        //     //TODO: do things
-        //
-        // }
-        println!("{}\n", serde_json::to_string(&output).unwrap());
-        std::io::stdout().flush().unwrap();
-        state.tick();
+        let written = serde_json::to_string(&output)
+            .map_err(BotError::from)
+            .and_then(|output| {
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{output}\n")?;
+                stdout.flush()?;
+                Ok(())
+            });
+        if let Err(err) = written {
+            error!(target: "io", "could not write output: {err}");
+            break;
+        }
+    }
+    bot.finish();
+    if let Some(path) = &args.metrics {
+        if let Err(err) = bot.metrics.write_report(path) {
+            error!(target: "metrics", "could not write metrics to {}: {err}", path.display());
+        }
     }
 }
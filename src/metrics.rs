@@ -0,0 +1,128 @@
+use std::{fmt, fs::File, io::{self, BufWriter}, path::Path, time::Duration};
+
+use prettytable::{Table, Row, Cell};
+use serde::Serialize;
+
+use crate::{MAX_DURATION, HARD_MAX_DURATION};
+
+// size of the flow graph a strategy solved, and the cost of the solution
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct GraphMetrics {
+    pub nodes: usize,
+    pub edges: usize,
+    pub cost: i64,
+}
+
+// Measurements of a single turn, durations in milliseconds. Strategies can add their own
+// measurements through `Strategy::report_metrics`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TurnMetrics {
+    pub turn: i64,
+    pub update_ms: f64,
+    pub calculate_ms: f64,
+    // from receiving the input until the moves are ready, which is what the server limits
+    pub total_ms: f64,
+    pub predict_calls: u64,
+    pub graph: Option<GraphMetrics>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PhaseSummary {
+    pub phase: &'static str,
+    pub p50: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Summary {
+    pub turns: usize,
+    pub phases: Vec<PhaseSummary>,
+    pub over_soft_limit: usize,
+    pub over_hard_limit: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new();
+        table.set_titles(Row::new(["phase", "p50", "p95", "max"].into_iter().map(Cell::new).collect()));
+        for phase in &self.phases {
+            table.add_row(Row::new(vec![
+                Cell::new(phase.phase),
+                Cell::new(&format!("{:.2}", phase.p50)),
+                Cell::new(&format!("{:.2}", phase.p95)),
+                Cell::new(&format!("{:.2}", phase.max)),
+            ]));
+        }
+        write!(f, "{table}")?;
+        write!(
+            f, "{} turns, {} over {MAX_DURATION}ms, {} over {HARD_MAX_DURATION}ms",
+            self.turns, self.over_soft_limit, self.over_hard_limit
+        )
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Metrics {
+    pub turns: Vec<TurnMetrics>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, turn: TurnMetrics) {
+        self.turns.push(turn);
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut phases = Vec::new();
+        let mut add_phase = |phase, values: Vec<f64>| {
+            if let Some(summary) = summarize(phase, values) {
+                phases.push(summary);
+            }
+        };
+        add_phase("update (ms)", self.turns.iter().map(|turn| turn.update_ms).collect());
+        add_phase("calculate (ms)", self.turns.iter().map(|turn| turn.calculate_ms).collect());
+        add_phase("total (ms)", self.turns.iter().map(|turn| turn.total_ms).collect());
+        add_phase("predict calls", self.turns.iter().map(|turn| turn.predict_calls as f64).collect());
+        let graphs = self.turns.iter().filter_map(|turn| turn.graph).collect::<Vec<_>>();
+        add_phase("graph nodes", graphs.iter().map(|graph| graph.nodes as f64).collect());
+        add_phase("graph edges", graphs.iter().map(|graph| graph.edges as f64).collect());
+        add_phase("graph cost", graphs.iter().map(|graph| graph.cost as f64).collect());
+
+        let over = |limit: u64| self.turns.iter().filter(|turn| turn.total_ms > limit as f64).count();
+        Summary {
+            turns: self.turns.len(),
+            phases,
+            over_soft_limit: over(MAX_DURATION),
+            over_hard_limit: over(HARD_MAX_DURATION),
+        }
+    }
+
+    // the summary and every single turn, as JSON
+    pub fn write_report(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let report = serde_json::json!({
+            "summary": self.summary(),
+            "turns": self.turns,
+        });
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &report)?;
+        Ok(())
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn summarize(phase: &'static str, mut values: Vec<f64>) -> Option<PhaseSummary> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    // nearest rank
+    let percentile = |p: f64| values[((p * values.len() as f64).ceil() as usize).saturating_sub(1)];
+    Some(PhaseSummary {
+        phase,
+        p50: percentile(0.5),
+        p95: percentile(0.95),
+        max: values[values.len() - 1],
+    })
+}
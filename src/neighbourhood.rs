@@ -1,20 +1,22 @@
 use itertools::Itertools;
+use serde::Deserialize;
 
-use crate::{structs::Move, state::State};
+use crate::{structs::Move, state::State, strategy::Strategy, deadline::Deadline};
 
 
-
-pub struct NeighbourhoodAlrorithm {
-    pub id: Option<u8>,
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NeighbourhoodConfig {
+    // amount of nearest planets an allied planet interacts with
     pub neighbourhood_size: usize,
+    // amount of allied neighbours the surplus of a planet is spread over
     pub friendly_load_balancing: usize,
     pub look_ahead: usize,
 }
 
-impl Default for NeighbourhoodAlrorithm {
+impl Default for NeighbourhoodConfig {
     fn default() -> Self {
-        NeighbourhoodAlrorithm { 
-            id: Some(1), 
+        NeighbourhoodConfig { 
             neighbourhood_size: 7, 
             friendly_load_balancing: 2,
             look_ahead: 20,
@@ -22,24 +24,65 @@ impl Default for NeighbourhoodAlrorithm {
     }
 }
 
+impl NeighbourhoodConfig {
+    // only looks at the direct surroundings, used when the time budget of a turn is used up
+    pub fn cheap() -> Self {
+        NeighbourhoodConfig { 
+            neighbourhood_size: 3, 
+            friendly_load_balancing: 1,
+            look_ahead: 5,
+        }
+    }
+}
+
+pub struct NeighbourhoodAlrorithm {
+    pub config: NeighbourhoodConfig,
+}
 
 impl NeighbourhoodAlrorithm {
-    pub fn calculate(&mut self, state: &mut State) -> Vec<Move> {
-        let mut moves = Vec::new();
+    pub fn new(config: NeighbourhoodConfig) -> Self {
+        NeighbourhoodAlrorithm { config }
+    }
+}
+
+impl Default for NeighbourhoodAlrorithm {
+    fn default() -> Self {
+        NeighbourhoodAlrorithm::new(NeighbourhoodConfig::default())
+    }
+}
+
+
+impl Strategy for NeighbourhoodAlrorithm {
+    fn name(&self) -> &'static str {
+        "neighbourhood"
+    }
+
+    fn calculate(&mut self, state: &mut State, deadline: &Deadline) -> Vec<Move> {
+        let mut moves: Vec<Move> = Vec::new();
+        let mut planned_count = 0;
 
-        // TODO: for state, take into account currently planned moves
         // TODO: calculate surplus for each allied planet beforehand, and if it is negative treat
         // it as an enemy planet
         for origin_planet_id in 0..state.planet_names.len() {
+            if deadline.expired() {
+                // out of time, keep the moves of the planets handled so far
+                break;
+            }
+            // the moves of the planets handled so far are taken into account for this one, so
+            // two planets do not both attack the same target
+            for planned_move in &moves[planned_count..] {
+                state.plan_move(planned_move).ok();
+            }
+            planned_count = moves.len();
             let (origin_planet_owner, _origin_planet_fleet_size) = state.predict_planet(0, origin_planet_id);
-            if origin_planet_owner != self.id {
+            if origin_planet_owner != state.own_id() {
                 continue;
             }
 
             // TODO: check if all planets can be reached if only connecting the n nearest planets
             let mut nearest = state.nearest_planets[origin_planet_id]
                 .iter()
-                .take(self.neighbourhood_size)
+                .take(self.config.neighbourhood_size)
                 .map(|(distance, destination_planet_id)| {
                     let time_delta = distance.ceil() as i64; 
                     let (destination_owner, destination_fleet_size) = state.predict_planet(time_delta, *destination_planet_id);
@@ -48,18 +91,18 @@ impl NeighbourhoodAlrorithm {
                 .collect_vec();
 
             let mut enemies = nearest
-                .drain_filter(|(_, _, owner, _)| *owner != self.id)
+                .drain_filter(|(_, _, owner, _)| *owner != state.own_id())
                 .collect_vec();
 
 
-            let origin_surplus = (0..self.look_ahead).map(|ta| {
+            let origin_surplus = (0..self.config.look_ahead).map(|ta| {
                 let (owner, owner_fleet_size) = state.predict_planet(ta as i64, origin_planet_id);
-                if owner == Some(1) {
+                if owner == state.own_id() {
                     owner_fleet_size
                 } else {
                     -owner_fleet_size
                 }
-            }).min().unwrap();
+            }).min().unwrap_or(0);
             if origin_surplus <= 0 {
                 continue;
             }
@@ -113,7 +156,7 @@ impl NeighbourhoodAlrorithm {
                 nearest_scored.reverse();
 
 
-                let nearest_scored = &nearest_scored[0..usize::min(self.friendly_load_balancing, nearest_scored.len())];
+                let nearest_scored = &nearest_scored[0..usize::min(self.config.friendly_load_balancing, nearest_scored.len())];
                 let sum_risk: f32 = nearest_scored.iter()
                     .map(|(_, score)| *score)
                     .sum();
@@ -129,7 +172,9 @@ impl NeighbourhoodAlrorithm {
         }
         moves
     }
+}
 
+impl NeighbourhoodAlrorithm {
     fn calcualte_risk(&self, state: &State, destination_planet_id: usize) -> f32 {
         state.nearest_planets[destination_planet_id]
             .iter()
@@ -137,7 +182,7 @@ impl NeighbourhoodAlrorithm {
                 let (other_owner, other_fleet_size) = state.predict_planet(0, *other_planet_id);
                 (other_distance, other_planet_id, other_owner, other_fleet_size)
             })
-            .filter(|(_, _, owner, _)| *owner != self.id)
+            .filter(|(_, _, owner, _)| *owner != state.own_id())
             .map(|(other_distance, _, other_owner, other_fleet_size)| {
                 if other_owner.is_none() {
                     // nautral planets pose no risk
@@ -0,0 +1,94 @@
+use std::fmt;
+
+use crate::structs::{Expedition, GameSituation, Planet, PlayerId};
+
+// players that still own a planet or have ships underway
+pub fn alive_players(planets: &[Planet], expeditions: &[Expedition]) -> Vec<PlayerId> {
+    let mut players: Vec<_> = planets.iter()
+        .filter_map(|planet| planet.owner)
+        .chain(expeditions.iter().map(|expedition| expedition.owner))
+        .collect();
+    players.sort_unstable();
+    players.dedup();
+    players
+}
+
+// total amount of ships of a player, both on planets and underway
+pub fn ship_count(planets: &[Planet], expeditions: &[Expedition], player: PlayerId) -> i64 {
+    let on_planets: i64 = planets.iter()
+        .filter(|planet| planet.owner == Some(player))
+        .map(|planet| planet.ship_count)
+        .sum();
+    let underway: i64 = expeditions.iter()
+        .filter(|expedition| expedition.owner == player)
+        .map(|expedition| expedition.ship_count)
+        .sum();
+    on_planets + underway
+}
+
+// The last player standing wins. When the turn limit is reached the player with the most
+// ships wins, `None` means a draw.
+pub fn winner(planets: &[Planet], expeditions: &[Expedition]) -> Option<PlayerId> {
+    let alive = alive_players(planets, expeditions);
+    if alive.len() <= 1 {
+        return alive.first().copied();
+    }
+    let mut ship_counts = alive.iter()
+        .map(|&player| (ship_count(planets, expeditions, player), player))
+        .collect::<Vec<_>>();
+    ship_counts.sort_unstable_by(|a, b| b.cmp(a));
+    if ship_counts[0].0 == ship_counts[1].0 {
+        return None;
+    }
+    Some(ship_counts[0].1)
+}
+
+// The game ends when at most one player is left or the turn limit is reached
+pub fn situation(planets: &[Planet], expeditions: &[Expedition], player: PlayerId, turn_limit_reached: bool) -> GameSituation {
+    if alive_players(planets, expeditions).len() > 1 && !turn_limit_reached {
+        return GameSituation::Ongoing;
+    }
+    match winner(planets, expeditions) {
+        Some(winner) if winner == player => GameSituation::Won,
+        Some(_) => GameSituation::Lost,
+        None => GameSituation::Draw,
+    }
+}
+
+// what a player had at the end of the game
+#[derive(Debug, Clone)]
+pub struct PlayerSummary {
+    pub player: PlayerId,
+    pub planets: usize,
+    pub ships: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameSummary {
+    pub situation: GameSituation,
+    pub turns: i64,
+    pub players: Vec<PlayerSummary>,
+}
+
+impl GameSummary {
+    pub fn new(planets: &[Planet], expeditions: &[Expedition], situation: GameSituation, turns: i64) -> Self {
+        let players = alive_players(planets, expeditions).into_iter()
+            .map(|player| PlayerSummary {
+                player,
+                planets: planets.iter().filter(|planet| planet.owner == Some(player)).count(),
+                ships: ship_count(planets, expeditions, player),
+            })
+            .collect();
+        GameSummary { situation, turns, players }
+    }
+}
+
+impl fmt::Display for GameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} after {} turns", self.situation, self.turns)?;
+        for PlayerSummary { player, planets, ships } in &self.players {
+            write!(f, ", player {player}: {planets} planets {ships} ships")?;
+        }
+        Ok(())
+    }
+}
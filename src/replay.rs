@@ -0,0 +1,275 @@
+use std::{fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, path::Path, time::Duration};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    bot::Bot,
+    deadline::Deadline,
+    error::BotError,
+    structs::{Input, Move, PlayerId},
+    MAX_TURNS,
+};
+
+// The first line of a replay: how the bot was configured, so it can be played again the same way
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayHeader {
+    pub strategy: String,
+    pub player_id: PlayerId,
+    // turn limit of the game, replays without one were played to the limit of the server
+    #[serde(default = "default_max_turns")]
+    pub max_turns: u64,
+    // the parameters from the config file and the command line, the rest were defaults
+    pub params: Map<String, Value>,
+}
+
+fn default_max_turns() -> u64 {
+    MAX_TURNS
+}
+
+impl ReplayHeader {
+    // The strategy and parameters to replay with. The recorded ones are used unless another
+    // strategy is chosen, given parameters override the recorded ones.
+    pub fn resolve(&self, strategy: Option<&str>, overrides: &Map<String, Value>) -> (String, Map<String, Value>) {
+        match strategy {
+            Some(strategy) if strategy != self.strategy => (strategy.to_string(), overrides.clone()),
+            _ => {
+                let mut params = self.params.clone();
+                params.extend(overrides.clone());
+                (self.strategy.clone(), params)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub turns: Vec<ReplayTurn>,
+}
+
+// one turn of a recorded game, written as a single line of JSON
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayTurn {
+    pub turn: u64,
+    pub strategy: String,
+    pub player_id: PlayerId,
+    // missing when the line of the turn could not be read, the bot skipped it
+    pub input: Option<Input>,
+    pub moves: Vec<Move>,
+    // time from receiving the input until the moves were ready
+    pub duration_ms: u64,
+    // set when the bot answered with no moves because the turn failed
+    pub error: Option<String>,
+}
+
+// Writes the header and then every turn the bot plays to a JSONL file
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+    strategy: String,
+    player_id: PlayerId,
+    turn: u64,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: impl AsRef<Path>, header: &ReplayHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, header)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(ReplayRecorder {
+            writer,
+            strategy: header.strategy.clone(),
+            player_id: header.player_id,
+            turn: 0,
+        })
+    }
+
+    // Every turn is flushed right away, so the replay is complete up to the last turn even
+    // when the bot is killed.
+    pub fn record(&mut self, input: Input, result: &Result<Vec<Move>, BotError>, duration: Duration) -> io::Result<()> {
+        let moves = result.as_ref().cloned().unwrap_or_default();
+        let error = result.as_ref().err().map(|err| err.to_string());
+        self.write(Some(input), moves, error, duration)
+    }
+
+    // a turn whose input could not be read, the bot still counted it
+    pub fn record_skipped(&mut self, err: &BotError, duration: Duration) -> io::Result<()> {
+        self.write(None, Vec::new(), Some(err.to_string()), duration)
+    }
+
+    fn write(&mut self, input: Option<Input>, moves: Vec<Move>, error: Option<String>, duration: Duration) -> io::Result<()> {
+        let turn = ReplayTurn {
+            turn: self.turn,
+            strategy: self.strategy.clone(),
+            player_id: self.player_id,
+            input,
+            moves,
+            duration_ms: duration.as_millis() as u64,
+            error,
+        };
+        self.turn += 1;
+        serde_json::to_writer(&mut self.writer, &turn)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+// Replays recorded before there was a header get one from their first turn, without parameters
+pub fn read_replay(path: impl AsRef<Path>) -> io::Result<Replay> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut header = None;
+    let mut turns = Vec::new();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if header.is_none() && turns.is_empty() {
+            if let Ok(recorded) = serde_json::from_str::<ReplayHeader>(&line) {
+                header = Some(recorded);
+                continue;
+            }
+        }
+        let turn: ReplayTurn = serde_json::from_str(&line).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {err}", line_number + 1))
+        })?;
+        turns.push(turn);
+    }
+    let header = match (header, turns.first()) {
+        (Some(header), _) => header,
+        (None, Some(first_turn)) => ReplayHeader {
+            strategy: first_turn.strategy.clone(),
+            player_id: first_turn.player_id,
+            max_turns: MAX_TURNS,
+            params: Map::new(),
+        },
+        (None, None) => return Err(io::Error::new(io::ErrorKind::InvalidData, "replay contains no turns")),
+    };
+    Ok(Replay { header, turns })
+}
+
+// a turn where the replayed bot did not send the recorded moves
+#[derive(Debug, Clone)]
+pub struct MoveDiff {
+    pub turn: u64,
+    pub recorded: Vec<Move>,
+    pub replayed: Vec<Move>,
+}
+
+// Feeds the recorded inputs to the bot in order, turns that could not be read are skipped as
+// they were in the game. The deadline never expires, so the outcome does not depend on the
+// speed of the machine. The order of the moves within a turn is ignored.
+pub fn replay(turns: &[ReplayTurn], bot: &mut Bot) -> Vec<MoveDiff> {
+    let mut diffs = Vec::new();
+    for turn in turns {
+        let Some(input) = &turn.input else {
+            bot.skip_turn();
+            continue;
+        };
+        let replayed = bot.play_turn(input.clone(), Deadline::unlimited())
+            .unwrap_or_else(|err| {
+                warn!("turn {} failed: {err}", turn.turn);
+                Vec::new()
+            });
+        if sorted(&turn.moves) != sorted(&replayed) {
+            diffs.push(MoveDiff { turn: turn.turn, recorded: turn.moves.clone(), replayed });
+        }
+    }
+    bot.finish();
+    diffs
+}
+
+fn sorted(moves: &[Move]) -> Vec<&Move> {
+    let mut moves = moves.iter().collect::<Vec<_>>();
+    moves.sort_by(|a, b| (&a.origin, &a.destination, a.ship_count).cmp(&(&b.origin, &b.destination, b.ship_count)));
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, time::Duration};
+
+    use serde_json::{Map, json};
+
+    use super::{ReplayHeader, ReplayRecorder, read_replay, replay};
+    use crate::{bot::Bot, deadline::Deadline, sim::{Game, generate_map}, strategy::build_strategy};
+
+    #[test]
+    fn recorded_game_replays_without_differences() {
+        let path = env::temp_dir().join(format!("enceladus-replay-{}.jsonl", std::process::id()));
+        let params = json!({ "neighbourhood_size": 3, "look_ahead": 12 }).as_object().unwrap().clone();
+        let header = ReplayHeader { strategy: "neighbourhood".to_string(), player_id: 1, max_turns: 30, params: params.clone() };
+        let mut recorder = ReplayRecorder::create(&path, &header).unwrap();
+        let mut bot = Bot::new(build_strategy("neighbourhood", params.clone()).unwrap(), 1, 30);
+        let mut opponent = Bot::new(build_strategy("simple", Map::new()).unwrap(), 1, 30);
+        let mut game = Game::new(generate_map(7, 5), 30).unwrap();
+        while !game.is_finished() {
+            let input = game.player_input(1);
+            let moves = bot.play_turn(input.clone(), Deadline::unlimited());
+            recorder.record(input, &moves, Duration::ZERO).unwrap();
+            let opponent_moves = opponent.play_turn(game.player_input(2), Deadline::unlimited()).unwrap();
+            game.dispatch_moves(1, &moves.unwrap());
+            game.dispatch_moves(2, &opponent_moves);
+            game.step();
+        }
+
+        let recorded = read_replay(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(recorded.header.params, params);
+        assert_eq!(recorded.turns.len(), 30);
+        let (strategy, params) = recorded.header.resolve(None, &Map::new());
+        let mut replayed = Bot::new(build_strategy(&strategy, params).unwrap(), recorded.header.player_id, recorded.header.max_turns);
+        assert!(replay(&recorded.turns, &mut replayed).is_empty());
+    }
+
+    #[test]
+    fn arguments_override_the_recorded_parameters() {
+        let header = ReplayHeader {
+            strategy: "neighbourhood".to_string(),
+            player_id: 1,
+            max_turns: 30,
+            params: json!({ "neighbourhood_size": 3, "look_ahead": 12 }).as_object().unwrap().clone(),
+        };
+        let overrides = json!({ "look_ahead": 20 }).as_object().unwrap().clone();
+        let (strategy, params) = header.resolve(Some("neighbourhood"), &overrides);
+        assert_eq!(strategy, "neighbourhood");
+        assert_eq!(params, *json!({ "neighbourhood_size": 3, "look_ahead": 20 }).as_object().unwrap());
+        let (strategy, params) = header.resolve(Some("simple"), &Map::new());
+        assert_eq!((strategy.as_str(), params.len()), ("simple", 0));
+    }
+
+    #[test]
+    fn skipped_turns_are_skipped_in_the_replay() {
+        let path = env::temp_dir().join(format!("enceladus-replay-skipped-{}.jsonl", std::process::id()));
+        let header = ReplayHeader { strategy: "simple".to_string(), player_id: 1, max_turns: 30, params: Map::new() };
+        let mut recorder = ReplayRecorder::create(&path, &header).unwrap();
+        let mut bot = Bot::new(build_strategy("simple", Map::new()).unwrap(), 1, 30);
+        let mut game = Game::new(generate_map(3, 5), 30).unwrap();
+        while !game.is_finished() {
+            let line = if game.turn == 10 { "{ garbage".to_string() } else { game.player_input_json(1) };
+            let moves = match bot.parse_input(&line) {
+                Ok(input) => {
+                    let moves = bot.play_turn(input.clone(), Deadline::unlimited());
+                    recorder.record(input, &moves, Duration::ZERO).unwrap();
+                    moves.unwrap()
+                },
+                Err(err) => {
+                    recorder.record_skipped(&err, Duration::ZERO).unwrap();
+                    Vec::new()
+                },
+            };
+            game.dispatch_moves(1, &moves);
+            game.step();
+        }
+
+        let recorded = read_replay(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(recorded.turns.iter().filter(|turn| turn.input.is_none()).count(), 1);
+        let mut replayed = Bot::new(build_strategy("simple", Map::new()).unwrap(), 1, 30);
+        assert!(replay(&recorded.turns, &mut replayed).is_empty());
+        assert_eq!(replayed.state.unwrap().turn, bot.state.unwrap().turn);
+    }
+}
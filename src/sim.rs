@@ -0,0 +1,415 @@
+use std::{collections::BTreeMap, fmt, panic::{self, AssertUnwindSafe}, time::Instant};
+
+use crate::{
+    bot::Bot,
+    combat::resolve_combat,
+    outcome,
+    deadline::Deadline,
+    structs::{Input, Planet, Expedition, Move, PlayerId, PlanetName, PlanetLocation, ExpeditionId, GameSituation},
+};
+
+#[derive(Debug, Clone)]
+pub enum DispatchError {
+    UnknownPlanet(PlanetName),
+    NotOwner(PlanetName),
+    SameOriginAndDestination(PlanetName),
+    InvalidShipCount(i64),
+    NotEnoughShips { planet: PlanetName, available: i64, requested: i64 },
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::UnknownPlanet(name) => write!(f, "unknown planet '{name}'"),
+            DispatchError::NotOwner(name) => write!(f, "planet '{name}' is not owned by the player"),
+            DispatchError::SameOriginAndDestination(name) => write!(f, "origin and destination are both '{name}'"),
+            DispatchError::InvalidShipCount(count) => write!(f, "invalid ship count {count}"),
+            DispatchError::NotEnoughShips { planet, available, requested } => {
+                write!(f, "planet '{planet}' has {available} ships, {requested} requested")
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    NoPlayers,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::NoPlayers => write!(f, "no planet or expedition is owned by a player"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+// A local Planet Wars engine. Player ids are absolute here, `player_input` converts the
+// state to the perspective of a single player, as the server does.
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub planets: Vec<Planet>,
+    pub expeditions: Vec<Expedition>,
+    pub player_count: u8,
+    pub turn: u64,
+    pub max_turns: u64,
+    planet_map: BTreeMap<PlanetName, usize>,
+    next_expedition_id: ExpeditionId,
+}
+
+impl Game {
+    // The amount of players is taken from the highest owner id on the map, a map without any
+    // player can not be played.
+    pub fn new(map: Input, max_turns: u64) -> Result<Self, MapError> {
+        let player_count = map.planets.iter()
+            .filter_map(|planet| planet.owner)
+            .chain(map.expeditions.iter().map(|expedition| expedition.owner))
+            .max()
+            .filter(|&player_count| player_count > 0)
+            .ok_or(MapError::NoPlayers)?;
+        let planet_map = map.planets.iter()
+            .enumerate()
+            .map(|(index, planet)| (planet.name.clone(), index))
+            .collect();
+        let next_expedition_id = map.expeditions.iter()
+            .map(|expedition| expedition.id + 1)
+            .max()
+            .unwrap_or(0);
+
+        Ok(Game {
+            planets: map.planets,
+            expeditions: map.expeditions,
+            player_count,
+            turn: 0,
+            max_turns,
+            planet_map,
+            next_expedition_id,
+        })
+    }
+
+    // converts an absolute player id so that `player` always sees itself as player 1
+    pub fn perspective(&self, owner: PlayerId, player: PlayerId) -> PlayerId {
+        (owner + self.player_count - player) % self.player_count + 1
+    }
+
+    pub fn player_input(&self, player: PlayerId) -> Input {
+        let planets = self.planets.iter()
+            .map(|planet| Planet {
+                owner: planet.owner.map(|owner| self.perspective(owner, player)),
+                ..planet.clone()
+            })
+            .collect();
+        let expeditions = self.expeditions.iter()
+            .map(|expedition| Expedition {
+                owner: self.perspective(expedition.owner, player),
+                ..expedition.clone()
+            })
+            .collect();
+        Input { planets, expeditions }
+    }
+
+    pub fn player_input_json(&self, player: PlayerId) -> String {
+        serde_json::to_string(&self.player_input(player)).unwrap()
+    }
+
+    // Sends out the moves of a player. Invalid moves are skipped, like the server does, and
+    // returned together with the reason they were rejected.
+    pub fn dispatch_moves(&mut self, player: PlayerId, moves: &[Move]) -> Vec<(usize, DispatchError)> {
+        let mut rejected = Vec::new();
+        for (index, player_move) in moves.iter().enumerate() {
+            if let Err(err) = self.dispatch_move(player, player_move) {
+                rejected.push((index, err));
+            }
+        }
+        rejected
+    }
+
+    fn dispatch_move(&mut self, player: PlayerId, player_move: &Move) -> Result<(), DispatchError> {
+        let origin_id = *self.planet_map.get(&player_move.origin)
+            .ok_or_else(|| DispatchError::UnknownPlanet(player_move.origin.clone()))?;
+        let destination_id = *self.planet_map.get(&player_move.destination)
+            .ok_or_else(|| DispatchError::UnknownPlanet(player_move.destination.clone()))?;
+        if origin_id == destination_id {
+            return Err(DispatchError::SameOriginAndDestination(player_move.origin.clone()));
+        }
+        if player_move.ship_count <= 0 {
+            return Err(DispatchError::InvalidShipCount(player_move.ship_count));
+        }
+        let origin = &self.planets[origin_id];
+        if origin.owner != Some(player) {
+            return Err(DispatchError::NotOwner(origin.name.clone()));
+        }
+        if origin.ship_count < player_move.ship_count {
+            return Err(DispatchError::NotEnoughShips {
+                planet: origin.name.clone(),
+                available: origin.ship_count,
+                requested: player_move.ship_count,
+            });
+        }
+
+        let distance = PlanetLocation::from(&self.planets[origin_id])
+            .distance(&PlanetLocation::from(&self.planets[destination_id]));
+        self.planets[origin_id].ship_count -= player_move.ship_count;
+        self.expeditions.push(Expedition {
+            id: self.next_expedition_id,
+            ship_count: player_move.ship_count,
+            origin: player_move.origin.clone(),
+            destination: player_move.destination.clone(),
+            owner: player,
+            turns_remaining: distance.ceil() as i64,
+        });
+        self.next_expedition_id += 1;
+        Ok(())
+    }
+
+    // Advances the game a single turn: owned planets grow by one ship, expeditions move one
+    // step closer and the ones that arrive fight for their destination.
+    pub fn step(&mut self) {
+        self.turn += 1;
+        for planet in self.planets.iter_mut() {
+            if planet.owner.is_some() {
+                planet.ship_count += 1;
+            }
+        }
+
+        let mut arrivals: BTreeMap<usize, Vec<(Option<PlayerId>, i64)>> = BTreeMap::new();
+        for expedition in self.expeditions.iter_mut() {
+            expedition.turns_remaining -= 1;
+        }
+        for expedition in self.expeditions.drain_filter(|expedition| expedition.turns_remaining <= 0) {
+            let planet_id = self.planet_map[&expedition.destination];
+            arrivals.entry(planet_id)
+                .or_default()
+                .push((Some(expedition.owner), expedition.ship_count));
+        }
+
+        for (planet_id, forces) in arrivals {
+            let planet = &mut self.planets[planet_id];
+            (planet.owner, planet.ship_count) = resolve_combat((planet.owner, planet.ship_count), forces);
+        }
+    }
+
+    // players that still own a planet or have ships underway
+    pub fn alive_players(&self) -> Vec<PlayerId> {
+        outcome::alive_players(&self.planets, &self.expeditions)
+    }
+
+    // total amount of ships of a player, both on planets and underway
+    pub fn ship_count(&self, player: PlayerId) -> i64 {
+        outcome::ship_count(&self.planets, &self.expeditions, player)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.alive_players().len() <= 1 || self.turn >= self.max_turns
+    }
+
+    // see `outcome::winner`
+    pub fn winner(&self) -> Option<PlayerId> {
+        outcome::winner(&self.planets, &self.expeditions)
+    }
+
+    pub fn situation(&self, player: PlayerId) -> GameSituation {
+        outcome::situation(&self.planets, &self.expeditions, player, self.turn >= self.max_turns)
+    }
+}
+
+// Anything that can take part in a game: it receives the state from its own perspective and
+// answers with its moves. An error forfeits the game.
+pub trait Player {
+    fn play_turn(&mut self, input: Input) -> Result<Vec<Move>, String>;
+
+    fn game_over(&mut self) {}
+}
+
+impl Player for Bot {
+    fn play_turn(&mut self, input: Input) -> Result<Vec<Move>, String> {
+        let deadline = Deadline::for_turn(Instant::now());
+        panic::catch_unwind(AssertUnwindSafe(|| Bot::play_turn(self, input, deadline)))
+            .map_err(|err| {
+                let message = err.downcast_ref::<&str>().map(|message| message.to_string())
+                    .or_else(|| err.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                format!("strategy panicked: {message}")
+            })?
+            .map_err(|err| err.to_string())
+    }
+
+    fn game_over(&mut self) {
+        self.finish();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    pub winner: Option<PlayerId>,
+    pub turns: u64,
+    // players that forfeited the game, with the reason
+    pub forfeits: Vec<(PlayerId, String)>,
+}
+
+// Plays a full game, `players[0]` is player 1, `players[1]` player 2 and so on
+pub fn run_match(map: Input, players: &mut [&mut dyn Player], max_turns: u64) -> Result<MatchResult, MapError> {
+    let mut game = Game::new(map, max_turns)?;
+    game.player_count = game.player_count.max(players.len() as u8);
+    let mut forfeits = Vec::new();
+
+    while !game.is_finished() {
+        let mut turn_moves = Vec::new();
+        for (index, player) in players.iter_mut().enumerate() {
+            let player_id = index as PlayerId + 1;
+            match player.play_turn(game.player_input(player_id)) {
+                Ok(moves) => turn_moves.push((player_id, moves)),
+                Err(reason) => forfeits.push((player_id, reason)),
+            }
+        }
+        if !forfeits.is_empty() {
+            break;
+        }
+        for (player_id, moves) in turn_moves {
+            game.dispatch_moves(player_id, &moves);
+        }
+        game.step();
+    }
+
+    for player in players.iter_mut() {
+        player.game_over();
+    }
+
+    let winner = if forfeits.is_empty() {
+        game.winner()
+    } else {
+        let remaining = (1..=players.len() as PlayerId)
+            .filter(|player_id| forfeits.iter().all(|(forfeited, _)| forfeited != player_id))
+            .collect::<Vec<_>>();
+        match remaining.as_slice() {
+            [player_id] => Some(*player_id),
+            _ => None,
+        }
+    };
+    Ok(MatchResult { winner, turns: game.turn, forfeits })
+}
+
+// xorshift64*, enough to generate reproducible maps
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must never be zero
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // uniform in [low, high)
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next_u64() % (high - low) as u64) as i64
+    }
+}
+
+// Generates a two player map that is point symmetric around the origin, so that neither
+// starting position has an advantage.
+pub fn generate_map(seed: u64, planet_pairs: usize) -> Input {
+    let mut rng = Rng::new(seed);
+    let mut planets = Vec::new();
+    for index in 0..planet_pairs {
+        let x = rng.range(-200, 200) as f32 / 10.0;
+        let y = rng.range(0, 200) as f32 / 10.0 + 1.0;
+        let (owners, ship_count) = if index == 0 {
+            ((Some(1), Some(2)), 50)
+        } else {
+            ((None, None), rng.range(1, 40))
+        };
+        planets.push(Planet { ship_count, x, y, owner: owners.0, name: format!("{seed}-{index}a") });
+        planets.push(Planet { ship_count, x: -x, y: -y, owner: owners.1, name: format!("{seed}-{index}b") });
+    }
+    Input { planets, expeditions: vec![] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Game, MapError};
+    use crate::{structs::Move, test_util::input};
+
+    fn send(origin: &str, destination: &str, ship_count: i64) -> Move {
+        Move { origin: origin.to_string(), destination: destination.to_string(), ship_count }
+    }
+
+    #[test]
+    fn maps_without_players_are_rejected() {
+        let map = input(&[("a", None, 5), ("b", None, 3)], vec![]);
+        assert_eq!(Game::new(map, 10).unwrap_err(), MapError::NoPlayers);
+    }
+
+    #[test]
+    fn moves_become_expeditions() {
+        let mut game = Game::new(input(&[("a", Some(1), 10), ("b", None, 3), ("c", Some(2), 4)], vec![]), 50).unwrap();
+        let rejected = game.dispatch_moves(1, &[send("a", "c", 6), send("a", "b", 5), send("c", "a", 1)]);
+        assert_eq!(rejected.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(game.planets[0].ship_count, 4);
+        assert_eq!(game.expeditions.len(), 1);
+        let expedition = &game.expeditions[0];
+        assert_eq!((expedition.owner, expedition.ship_count, expedition.turns_remaining), (1, 6, 20));
+        assert_eq!(expedition.destination, "c");
+    }
+
+    #[test]
+    fn owned_planets_grow() {
+        let mut game = Game::new(input(&[("a", Some(1), 10), ("b", None, 3), ("c", Some(2), 4)], vec![]), 50).unwrap();
+        game.step();
+        game.step();
+        let ship_counts = game.planets.iter().map(|planet| planet.ship_count).collect::<Vec<_>>();
+        assert_eq!(ship_counts, vec![12, 3, 6]);
+        assert_eq!(game.turn, 2);
+    }
+
+    #[test]
+    fn expeditions_fight_on_arrival() {
+        let mut game = Game::new(input(&[("a", Some(1), 10), ("b", None, 3), ("c", Some(2), 4)], vec![]), 50).unwrap();
+        assert!(game.dispatch_moves(1, &[send("a", "b", 5)]).is_empty());
+        for _ in 0..9 {
+            game.step();
+        }
+        assert_eq!(game.expeditions[0].turns_remaining, 1);
+        assert_eq!((game.planets[1].owner, game.planets[1].ship_count), (None, 3));
+        game.step();
+        assert!(game.expeditions.is_empty());
+        assert_eq!((game.planets[1].owner, game.planets[1].ship_count), (Some(1), 2));
+    }
+
+    #[test]
+    fn game_ends_with_the_last_player() {
+        let mut game = Game::new(input(&[("a", Some(1), 30), ("b", Some(2), 2)], vec![]), 50).unwrap();
+        assert!(game.dispatch_moves(1, &[send("a", "b", 25)]).is_empty());
+        assert!(!game.is_finished());
+        for _ in 0..10 {
+            game.step();
+        }
+        assert_eq!(game.alive_players(), vec![1]);
+        assert!(game.is_finished());
+        assert_eq!(game.winner(), Some(1));
+    }
+
+    #[test]
+    fn game_ends_at_the_turn_limit() {
+        let mut game = Game::new(input(&[("a", Some(1), 10), ("b", Some(2), 2)], vec![]), 3).unwrap();
+        for _ in 0..3 {
+            assert!(!game.is_finished());
+            game.step();
+        }
+        assert!(game.is_finished());
+        assert_eq!(game.winner(), Some(1));
+    }
+}
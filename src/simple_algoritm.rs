@@ -1,36 +1,62 @@
 use itertools::Itertools;
+use log::trace;
 use priq::PriorityQueue;
+use serde::Deserialize;
 use smallvec::SmallVec;
 
-use crate::{state::State, structs::Move};
+use crate::{state::State, structs::Move, strategy::Strategy, deadline::Deadline};
 
-// TODO: make field of SimpleAlrorithm 
-const LOOK_AHEAD: usize = 20;
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimpleConfig {
+    pub look_ahead: usize,
+}
+
+impl Default for SimpleConfig {
+    fn default() -> Self {
+        SimpleConfig { look_ahead: 20 }
+    }
+}
 
-#[allow(dead_code)]
+#[derive(Default)]
 pub struct SimpleAlrorithm {
+    pub config: SimpleConfig,
     // first index is planet, second is time
     // scores: VecMap<VecMap<f32>>
 
 }
 
-
-#[allow(dead_code)]
 impl SimpleAlrorithm {
-    pub fn calculate(&mut self, state: &mut State) -> Vec<Move> {
+    pub fn new(config: SimpleConfig) -> Self {
+        SimpleAlrorithm { config }
+    }
+}
+
+
+impl Strategy for SimpleAlrorithm {
+    fn name(&self) -> &'static str {
+        "simple"
+    }
+
+    fn calculate(&mut self, state: &mut State, deadline: &Deadline) -> Vec<Move> {
         // self.scores.clear();
-        let mut moves = Vec::new();
+        let mut moves: Vec<Move> = Vec::new();
+        let mut planned_count = 0;
 
 
-        let mut queue = self.calculate_scores(state).into_sorted_vec();
+        let mut queue = self.calculate_scores(state, deadline).into_sorted_vec();
         queue.reverse(); //make sure that the scores go from hight to low
-        // dbg!(&state);
-        // eprintln!("QUEUE {queue:?}");
+        trace!("queue: {queue:?}");
         for (score, (destination_planet_id, turns_ahead)) in queue.iter() {
-            if *score <= 0.0 {
+            if *score <= 0.0 || deadline.expired() {
                 break;
             }
-            // eprintln!("PLANET {planet_id:?}, TURNS_AHEAD: {turns_ahead:?}");
+            // ships that already leave an origin lower its deficit for the next destinations
+            for planned_move in &moves[planned_count..] {
+                state.plan_move(planned_move).ok();
+            }
+            planned_count = moves.len();
+            trace!("destination: {destination_planet_id}, turns ahead: {turns_ahead}");
             
             let nearest: SmallVec<[_; 3]> = state.nearest_planets[*destination_planet_id]
                 .iter()
@@ -38,7 +64,7 @@ impl SimpleAlrorithm {
                     let (owner, fleet_size) = state.predict_planet(*turns_ahead as i64, *other_planet_id);
                     (distance, other_planet_id, owner, fleet_size)
                 }) 
-                .filter(|(_, _, owner, _)| *owner == Some(1)) // TODO: change Some(1) to variable stored in self
+                .filter(|(_, _, owner, _)| *owner == state.own_id())
                 .sorted_by_key(|(distance, _other_planet_id, _owner, fleet_size)| -fleet_size + distance.ceil() as i64)
                 .take(3)
                 .collect();
@@ -50,14 +76,14 @@ impl SimpleAlrorithm {
             let (origin_distance, origin_planet_id, _, _) = nearest[0];
             // let origin_fleet_size = state.predict_planet(0, *origin_planet_id).1;
 
-            let origin_deficit = (0..LOOK_AHEAD).map(|ta| {
+            let origin_deficit = (0..self.config.look_ahead).map(|ta| {
                 let (owner, owner_fleet_size) = state.predict_planet(ta as i64, *origin_planet_id);
-                if owner == Some(1) {
+                if owner == state.own_id() {
                     owner_fleet_size
                 } else {
                     -owner_fleet_size
                 }
-            }).min().unwrap();
+            }).min().unwrap_or(0);
             if origin_deficit <= 0 {
                 continue;
             }
@@ -69,7 +95,7 @@ impl SimpleAlrorithm {
                     let (owner, fleet_size) = state.predict_planet(*turns_ahead as i64, *other_planet_id);
                     (distance, other_planet_id, owner, fleet_size)
                 })
-                .filter(|(distance, _, owner, _)| **distance < 10.0 && *owner != Some(1) && owner.is_some()) // TODO: change Some(1) to variable stored in self
+                .filter(|(distance, _, owner, _)| **distance < 10.0 && *owner != state.own_id() && owner.is_some())
                 .take(1)
                 .collect_vec();
             if !nearest_enemy_vec.is_empty() {
@@ -94,7 +120,9 @@ impl SimpleAlrorithm {
         }
         moves
     }
+}
 
+impl SimpleAlrorithm {
     // fn set_score(&mut self, planet_id: usize, turns_ahead: usize, score: f32) {
     //     let planet_scores = self.scores.entry(planet_id)
     //         .or_insert_with(VecMap::new);
@@ -103,11 +131,14 @@ impl SimpleAlrorithm {
     // } 
 
     // first element of tuple is planet_id, second it turns ahead
-    fn calculate_scores(&mut self, state: &mut State) -> PriorityQueue<f32, (usize, usize)> {
+    fn calculate_scores(&mut self, state: &mut State, deadline: &Deadline) -> PriorityQueue<f32, (usize, usize)> {
         let mut queue = PriorityQueue::new();
         
         for planet_id in 0..state.planet_names.len() {
-            for turns_ahead in 0..LOOK_AHEAD {
+            if deadline.expired() {
+                break;
+            }
+            for turns_ahead in 0..self.config.look_ahead {
                 let score: f32 = self.calculate_score(planet_id, turns_ahead as i64, state);
                 queue.put(score, (planet_id, turns_ahead));
             }
@@ -118,7 +149,7 @@ impl SimpleAlrorithm {
 
     fn calculate_score(&self, planet_id: usize, turns_ahead: i64, state: &mut State) -> f32 {
         let (owner, fleet_size) = state.predict_planet(turns_ahead, planet_id);
-        if owner == Some(1) { // TODO: change Some(1) to variable stored in self
+        if owner == state.own_id() {
             return -3.0;
         }
         let nearest: SmallVec<[_; 3]> = state.nearest_planets[planet_id]
@@ -127,7 +158,7 @@ impl SimpleAlrorithm {
                 let (owner, fleet_size) = state.predict_planet(turns_ahead, *other_planet_id);
                (distance, other_planet_id, owner, fleet_size)
             }) 
-            .filter(|(_, _, owner, _)| *owner == Some(1)) // TODO: change Some(1) to variable stored in self
+            .filter(|(_, _, owner, _)| *owner == state.own_id())
             .take(1)
             .collect();
         if nearest.is_empty() {
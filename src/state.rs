@@ -1,29 +1,116 @@
 
-use std::collections::BTreeMap;
+use std::{cell, collections::{BTreeMap, BTreeSet, VecDeque}, fmt};
 
 use bit_set::BitSet;
 use itertools::Itertools;
+use log::{trace, warn};
 use prettytable::{Table, Cell, Row};
 use priq::PriorityQueue;
 
-use crate::{MAX_TURNS, structs::{GameSituation, Input, PlayerId, PlanetName, PlanetLocation, PlanetId, IntoPlanetId}};
+use crate::{combat::resolve_combat, error::StateError, outcome, structs::{Expedition, ExpeditionId, GameSituation, Input, Move, PlayerId, PlanetName, PlanetLocation, PlanetId, IntoPlanetId}};
 
-#[derive(Clone, Debug)]
+// log target of the prediction tables, which are too verbose for the rest of the state logs
+pub const PREDICTIONS_TARGET: &str = concat!(module_path!(), "::predictions");
+
+#[derive(Clone, Debug, Default)]
 pub struct StateCell {
     // TODO: use rust-smallvec https://crates.io/crates/smallvec
     deltas: Vec<(PlayerId, i64)>,
 }
 
+// where the delta of an observed expedition is stored
+#[derive(Clone, Debug)]
+struct TrackedExpedition {
+    arrival_turn: i64,
+    destination: PlanetId,
+    delta: (PlayerId, i64),
+}
+
+// predicted (owner, ship count) of a planet for every turn from `start_turn` on, up to the last
+// turn with deltas
+#[derive(Clone, Debug)]
+struct Timeline {
+    start_turn: i64,
+    entries: Vec<(Option<PlayerId>, i64)>,
+}
+
+impl Timeline {
+    // Turns before the start are clamped to it. After the last entry nothing arrives anymore,
+    // so the planet only grows when it has an owner.
+    fn at(&self, turn: i64) -> (Option<PlayerId>, i64) {
+        let index = (turn - self.start_turn).max(0) as usize;
+        if let Some(entry) = self.entries.get(index) {
+            return *entry;
+        }
+        let (owner, ship_count) = *self.entries.last().expect("a timeline contains its start turn");
+        let turns_after = (index + 1 - self.entries.len()) as i64;
+        match owner {
+            Some(_) => (owner, ship_count + turns_after),
+            None => (owner, ship_count),
+        }
+    }
+}
+
+// Moves planned this turn, which the server does not know about yet. They are kept apart from
+// the observed expeditions, so they can be dropped without touching the rest of the state.
+#[derive(Clone, Debug, Default)]
+struct Overlay {
+    moves: Vec<Move>,
+    // the first `committed` moves are kept by `discard_planned`
+    committed: usize,
+    // deltas of the planned moves, by (turn, planet_id)
+    deltas: BTreeMap<(i64, PlanetId), Vec<(PlayerId, i64)>>,
+    // timelines including the planned moves, only for the planets they affect
+    timelines: BTreeMap<PlanetId, Timeline>,
+}
+
 #[derive(Clone, Debug)]
 pub struct State {
-    pub state: Vec<Vec<StateCell>>,
+    // deltas by turn, the first row belongs to `first_turn`. Rows of passed turns are pruned
+    // and rows are added when something arrives later than everything before.
+    state: VecDeque<Vec<StateCell>>,
+    first_turn: i64,
     pub current_state: Input,
-    pub saved_expeditions: BitSet, 
+    // expeditions whose deltas are stored, by id
+    expeditions: BTreeMap<ExpeditionId, TrackedExpedition>,
     pub planet_map: BTreeMap<PlanetName, usize>,
     pub planet_names: Vec<PlanetName>,
     pub turn: i64,
+    // the last turn of the game, predictions do not change after it
+    pub max_turns: i64,
+    // the id the bot plays as
+    pub player_id: PlayerId,
     // maps planet_id to a list of planet_ids and distances, sorted by distance ascending
     pub nearest_planets: Vec<Vec<(f32, PlanetId)>>,
+    // cached predictions, indexed by planet_id
+    timelines: Vec<Timeline>,
+    overlay: Overlay,
+    // planets that did not match their prediction in the last update
+    pub drift: Vec<Drift>,
+    // calls of `predict_planet` since the last `take_predict_calls`
+    predict_calls: cell::Cell<u64>,
+}
+
+// A planet whose observation differs from what was predicted for this turn. Ships that left the
+// planet last turn without being planned here, like the moves of other players, are taken into
+// account, so any drift points at a mistake in the model. The timeline of the planet is rebuilt
+// from the observation, the stored deltas of the expeditions are left as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub turn: i64,
+    pub planet: PlanetName,
+    pub predicted: (Option<PlayerId>, i64),
+    pub observed: (Option<PlayerId>, i64),
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Drift { turn, planet, predicted, observed } = self;
+        write!(
+            f, "drift turn={turn} planet={planet} predicted_owner={:?} predicted_ships={} observed_owner={:?} observed_ships={}",
+            predicted.0, predicted.1, observed.0, observed.1
+        )
+    }
 }
 
 
@@ -32,11 +119,48 @@ impl State {
         self.turn += 1;
     }
 
-    pub fn get_state_cell(&mut self, planet_name: &PlanetName, turns_ahead: i64) -> &mut StateCell {
-        let planet_index = self.planet_map.get(planet_name).unwrap();
-        // TODO: check if correct or if it is off by one 
-        let turn_index = (self.turn + turns_ahead) as usize;
-        &mut self.state[turn_index][*planet_index]
+    // own player id, as it is used for the owner of planets
+    pub fn own_id(&self) -> Option<PlayerId> {
+        Some(self.player_id)
+    }
+
+    pub fn get_state_cell(&mut self, planet_name: &PlanetName, turns_ahead: i64) -> Result<&mut StateCell, StateError> {
+        let planet_index = *self.planet_map.get(planet_name)
+            .ok_or_else(|| StateError::UnknownPlanet(planet_name.clone()))?;
+        // an expedition that has `turns_remaining` left arrives that many turns from now, its
+        // ships are part of the observation of that turn. Nothing happens after the last turn,
+        // so no rows are stored for it.
+        let turn = self.turn.saturating_add(turns_ahead);
+        if turn < self.first_turn || turn > self.max_turns {
+            return Err(StateError::TurnOutOfRange(turn));
+        }
+        Ok(&mut self.row_mut(turn)[planet_index])
+    }
+
+    // the deltas of a turn, adding empty rows up to it when needed
+    fn row_mut(&mut self, turn: i64) -> &mut Vec<StateCell> {
+        let index = (turn - self.first_turn) as usize;
+        while self.state.len() <= index {
+            self.state.push_back(vec![StateCell::default(); self.planet_names.len()]);
+        }
+        &mut self.state[index]
+    }
+
+    fn deltas(&self, turn: i64, planet_index: PlanetId) -> &[(PlayerId, i64)] {
+        usize::try_from(turn - self.first_turn).ok()
+            .and_then(|index| self.state.get(index))
+            .map_or(&[], |row| &row[planet_index].deltas)
+    }
+
+    fn last_stored_turn(&self) -> i64 {
+        self.first_turn + self.state.len() as i64 - 1
+    }
+
+    // drops the deltas of the turns before the current one
+    fn prune(&mut self) {
+        let passed = ((self.turn - self.first_turn).max(0) as usize).min(self.state.len());
+        self.state.drain(..passed);
+        self.first_turn = self.turn;
     }
 
 
@@ -53,56 +177,134 @@ impl State {
         // &self.state[turn_index as usize]
     }
 
-    // TODO: cache results
+    // Lookup in the cached timeline of the planet, planned moves included. Zero turns ahead is
+    // the observed planet minus the ships planned to leave, predictions past the last turn of
+    // the game return the planet at the end of the game.
     pub fn predict_planet(&self, turns_ahead: i64, into_planet_id: impl IntoPlanetId) -> (Option<PlayerId>, i64) {
-        let turn_index = (self.turn + turns_ahead) as usize;
+        self.predict_calls.set(self.predict_calls.get() + 1);
         let planet_index = into_planet_id.into_planet_id(&self.planet_map);
-        let planet_state = &self.current_state.planets[planet_index];
+        let timeline = self.overlay.timelines.get(&planet_index)
+            .unwrap_or(&self.timelines[planet_index]);
+        timeline.at((self.turn + turns_ahead).min(self.max_turns))
+    }
 
-        let mut current_owner = planet_state.owner;
-        let mut current_count = planet_state.ship_count;
-        let mut map: BTreeMap<Option<u8>, i64> = BTreeMap::new();
-        for i in (self.turn as usize)..=turn_index {
-            map.clear();
-            // if not checking the current state and the owner of the planet is a player, then
-            // ship count will have grown with 1 
-            if (i as i64) != self.turn && current_owner.is_some() {
-                map.insert(current_owner, current_count + 1);
-            } else {
-                map.insert(current_owner, current_count);
-            }
+    // the number of predictions made since the last call
+    pub fn take_predict_calls(&self) -> u64 {
+        self.predict_calls.replace(0)
+    }
 
-            let deltas = &self.state[i][planet_index].deltas;
-            for &(owner, amount) in deltas {
-                let key = Some(owner);
-                let value = map.get(&key);
-                map.insert(key, value.unwrap_or(&0) + amount);
-            }
+    pub fn distance(&self, planet_id: PlanetId, other_planet_id: PlanetId) -> f32 {
+        let planets = &self.current_state.planets;
+        PlanetLocation::from(&planets[planet_id]).distance(&PlanetLocation::from(&planets[other_planet_id]))
+    }
 
-            let key_values = map.iter()
-                .sorted_by_key(|(_, &v)| -v) // sorts by ascending order, so for negative value
-                // sorts descending order
-                .take(2)
-                .collect_vec();
-
-            let (largest_owner, largest_count) = key_values[0];
-            if key_values.len() == 1 {
-                (current_owner, current_count) = (*largest_owner, *largest_count);
-            } else {
-                let (_, next_count) = key_values[1];
-                current_count = largest_count - next_count;
-                if current_count == 0 {
-                    current_owner = None;
-                } else {
-                    current_owner = *largest_owner;
-                }
-            }
+    // Adds a tentative move, which predictions take into account until it is discarded or the
+    // next turn starts. Its ships leave the origin this turn and arrive at the destination after
+    // the travel time.
+    pub fn plan_move(&mut self, planned_move: &Move) -> Result<(), StateError> {
+        let origin = *self.planet_map.get(&planned_move.origin)
+            .ok_or_else(|| StateError::UnknownPlanet(planned_move.origin.clone()))?;
+        let destination = *self.planet_map.get(&planned_move.destination)
+            .ok_or_else(|| StateError::UnknownPlanet(planned_move.destination.clone()))?;
+        if planned_move.ship_count <= 0 || origin == destination {
+            return Ok(());
+        }
+        let arrival_turn = self.turn + self.distance(origin, destination).ceil() as i64;
+        // timelines end at the last stored row, after the last turn of the game there is none
+        let last_row = arrival_turn.min(self.max_turns);
+        if last_row >= self.first_turn {
+            self.row_mut(last_row);
         }
 
-        (current_owner, current_count)
+        self.overlay.deltas.entry((self.turn, origin))
+            .or_default()
+            .push((self.player_id, -planned_move.ship_count));
+        self.overlay.deltas.entry((arrival_turn, destination))
+            .or_default()
+            .push((self.player_id, planned_move.ship_count));
+        self.overlay.moves.push(planned_move.clone());
+        for planet_id in [origin, destination] {
+            let timeline = self.compute_timeline(planet_id, true);
+            self.overlay.timelines.insert(planet_id, timeline);
+        }
+        Ok(())
     }
 
-    #[allow(dead_code)]
+    // keeps the planned moves for the rest of the turn
+    pub fn commit_planned(&mut self) {
+        self.overlay.committed = self.overlay.moves.len();
+    }
+
+    // drops the moves planned since the last commit
+    pub fn discard_planned(&mut self) {
+        if self.overlay.committed == self.overlay.moves.len() {
+            return;
+        }
+        let mut committed = std::mem::take(&mut self.overlay.moves);
+        committed.truncate(self.overlay.committed);
+        self.overlay = Overlay::default();
+        for planned_move in &committed {
+            self.plan_move(planned_move)
+                .expect("move was accepted before");
+        }
+        self.commit_planned();
+    }
+
+    pub fn planned_moves(&self) -> &[Move] {
+        &self.overlay.moves
+    }
+
+    // replays the deltas of a planet from the current turn up to the last stored turn, or the
+    // end of the game when that comes first
+    fn compute_timeline(&self, planet_index: PlanetId, with_overlay: bool) -> Timeline {
+        let planet_state = &self.current_state.planets[planet_index];
+
+        let last_turn = self.last_stored_turn().min(self.max_turns).max(self.turn);
+        let mut entries = Vec::with_capacity((last_turn - self.turn + 1) as usize);
+        let mut current = (planet_state.owner, planet_state.ship_count);
+        for turn in self.turn..=last_turn {
+            current = self.step_planet(planet_index, turn, current, with_overlay);
+            entries.push(current);
+        }
+
+        Timeline { start_turn: self.turn, entries }
+    }
+
+    // The (owner, ship count) of a planet at `turn`, given the one of the turn before. The
+    // observation of the current turn already contains its growth and arrivals, only the
+    // planned departures are missing from it.
+    fn step_planet(
+        &self,
+        planet_index: PlanetId,
+        turn: i64,
+        (current_owner, current_count): (Option<PlayerId>, i64),
+        with_overlay: bool,
+    ) -> (Option<PlayerId>, i64) {
+        let observed = turn == self.turn;
+        let garrison = if !observed && current_owner.is_some() {
+            (current_owner, current_count + 1)
+        } else {
+            (current_owner, current_count)
+        };
+
+        let deltas = if observed { &[] } else { self.deltas(turn, planet_index) };
+        let planned_deltas = self.overlay.deltas.get(&(turn, planet_index))
+            .filter(|_| with_overlay)
+            .into_iter()
+            .flatten();
+        let arrivals = deltas.iter()
+            .chain(planned_deltas)
+            .map(|&(owner, amount)| (Some(owner), amount));
+        resolve_combat(garrison, arrivals)
+    }
+
+    fn recompute_timelines(&mut self, planet_ids: impl IntoIterator<Item = PlanetId>) {
+        for planet_id in planet_ids {
+            self.timelines[planet_id] = self.compute_timeline(planet_id, false);
+        }
+    }
+
+    // logs a table with the predictions of the next turns, enabled with `state::predictions=trace`
     pub fn debug_print_predictions(&self) {
         let mut table = Table::new();
         let mut header = vec![Cell::new("turn")];
@@ -121,54 +323,53 @@ impl State {
             }
             table.add_row(Row::new(predict_row));
         }
-        eprintln!("{:?}", self.current_state.expeditions);
-        eprintln!("{table}");
+        trace!(target: PREDICTIONS_TARGET, "expeditions: {:?}", self.current_state.expeditions);
+        trace!(target: PREDICTIONS_TARGET, "\n{table}");
 
     }
 
-    #[allow(dead_code)]
-    pub fn check_gameover(&self) -> GameSituation {
-        // TODO: currently only checks units on planets and does not take expeditions into account 
-        let binding = self.predict_planets(1);
-        let ship_counts = binding
-            .iter()
-            .into_grouping_map_by(|(owner, _)| owner)
-            .fold(0, |acc, _key, (_, val)| acc + val);
-
-        // let temp = self.current_state.planets
-        //     .iter()
-        //     .into_grouping_map_by(|planet| planet.owner)
-        //     .fold(0, |acc, _key, val| acc + val.ship_count);
-        // eprintln!("current   : {:?}", self.current_state.expeditions);
-        // eprintln!("current   : {:?}", temp);
-        // eprintln!("prediction: {:?}", ship_counts);
-        if !ship_counts.contains_key(&Some(1)) {
-            return GameSituation::Lost;
-        } 
-
-        let other_key = ship_counts.keys()
-            .into_iter()
-            .find(|&&&key| key.is_some() && key != Some(1));
-        if other_key.is_none() {
-            return GameSituation::Won;
+    // Ships of other players underway, summed per turns ahead of their arrival and destination.
+    // Arrivals after the last turn of the game are left out.
+    pub fn hostile_arrivals(&self) -> BTreeMap<(i64, PlanetId), i64> {
+        let mut arrivals = BTreeMap::new();
+        for expedition in self.expeditions.values() {
+            let (owner, ship_count) = expedition.delta;
+            if owner == self.player_id || expedition.arrival_turn <= self.turn || expedition.arrival_turn > self.max_turns {
+                continue;
+            }
+            *arrivals.entry((expedition.arrival_turn - self.turn, expedition.destination)).or_insert(0) += ship_count;
         }
-        GameSituation::Ongoing 
-    } 
+        arrivals
+    }
+
+    // Evaluates the observed game, ships underway included. It is only won, lost or drawn once
+    // it is over: at most one player is left or the turn limit has been reached.
+    pub fn check_gameover(&self) -> GameSituation {
+        let Input { planets, expeditions } = &self.current_state;
+        outcome::situation(planets, expeditions, self.player_id, self.turn >= self.max_turns)
+    }
 
-    pub fn new(input: Input) -> Self {
-        let mut entry = vec![];
+    // The game is not over yet, but no other player owns a planet anymore and all their ships
+    // underway together are fewer than ours, so they can not come back.
+    pub fn is_decided(&self) -> bool {
+        let Input { planets, expeditions } = &self.current_state;
+        let others_own_planets = planets.iter()
+            .any(|planet| planet.owner.is_some() && planet.owner != self.own_id());
+        let other_ships: i64 = outcome::alive_players(planets, expeditions).into_iter()
+            .filter(|&player| player != self.player_id)
+            .map(|player| outcome::ship_count(planets, expeditions, player))
+            .sum();
+        !others_own_planets && outcome::ship_count(planets, expeditions, self.player_id) > other_ships
+    }
+
+    // `max_turns` is the turn limit of the game, predictions end there
+    pub fn new(input: Input, player_id: PlayerId, max_turns: u64) -> Self {
         let mut planet_map = BTreeMap::new();
         let mut planet_names = vec![];
         let mut planet_locations: Vec<PlanetLocation> = vec![];
         let mut nearest_planets = Vec::new();
 
         for (index, planet) in input.planets.iter().enumerate() {
-            entry.push(
-                StateCell {
-                    deltas: vec![],
-                }
-            );
-
             planet_map.insert(planet.name.clone(), index);
             planet_names.push(planet.name.clone());
             planet_locations.push(planet.into());
@@ -191,33 +392,369 @@ impl State {
 
 
 
-        let mut state_vec = vec![];
-        for _ in 0..MAX_TURNS {
-            state_vec.push(entry.clone());
-        }
-        State {
+        let planet_count = planet_names.len();
+        let mut state = State {
             nearest_planets,
             planet_names,
             current_state: input,
-            state: state_vec,
+            state: VecDeque::new(),
+            first_turn: 0,
             planet_map,
-            saved_expeditions: BitSet::new(),
+            expeditions: BTreeMap::new(),
             turn: 0,
+            max_turns: max_turns as i64,
+            player_id,
+            timelines: Vec::new(),
+            overlay: Overlay::default(),
+            drift: Vec::new(),
+            predict_calls: cell::Cell::new(0),
+        };
+        // fleets that are already underway when the bot starts
+        let expeditions = std::mem::take(&mut state.current_state.expeditions);
+        for expedition in &expeditions {
+            state.track_expedition(expedition, &mut BitSet::new());
         }
+        state.current_state.expeditions = expeditions;
+        state.timelines = (0..planet_count)
+            .map(|planet_id| state.compute_timeline(planet_id, false))
+            .collect();
+        state
     }
 
-    pub fn update(&mut self, mut input: Input) {
-        for expedition in &input.expeditions {
-            if self.saved_expeditions.contains(expedition.id as usize) {
+    // Forgets the expeditions that arrived. The ones that are missing from the input before
+    // their arrival will not arrive anymore, so their deltas are removed.
+    fn retire_expeditions(&mut self, input: &Input, dirty_planets: &mut BitSet) {
+        let in_flight = input.expeditions.iter()
+            .map(|expedition| expedition.id)
+            .collect::<BTreeSet<_>>();
+        let retired = self.expeditions.keys()
+            .filter(|id| !in_flight.contains(id))
+            .copied()
+            .collect_vec();
+        for id in retired {
+            let expedition = self.expeditions.remove(&id).expect("id was just found");
+            if expedition.arrival_turn <= self.turn || expedition.arrival_turn > self.max_turns {
                 continue;
-            } 
-            self.saved_expeditions.insert(expedition.id as usize);
-            let state_cell = self.get_state_cell(&expedition.destination, expedition.turns_remaining);
-            state_cell.deltas.push(
-                (expedition.owner, expedition.ship_count)
-            );
+            }
+            warn!("expedition {id} vanished before its arrival at turn {}", expedition.arrival_turn);
+            let deltas = &mut self.row_mut(expedition.arrival_turn)[expedition.destination].deltas;
+            if let Some(index) = deltas.iter().position(|delta| *delta == expedition.delta) {
+                deltas.remove(index);
+            }
+            dirty_planets.insert(expedition.destination);
+        }
+    }
+
+    // Stores the delta of an expedition that was not seen before. One that arrives after the last
+    // turn is tracked without a delta, it can not change the outcome anymore.
+    fn track_expedition(&mut self, expedition: &Expedition, dirty_planets: &mut BitSet) {
+        if self.expeditions.contains_key(&expedition.id) {
+            return;
+        }
+        let delta = (expedition.owner, expedition.ship_count);
+        let arrival_turn = self.turn.saturating_add(expedition.turns_remaining);
+        if arrival_turn <= self.max_turns {
+            let state_cell = match self.get_state_cell(&expedition.destination, expedition.turns_remaining) {
+                Ok(state_cell) => state_cell,
+                Err(err) => {
+                    warn!("ignoring expedition {}: {err}", expedition.id);
+                    return;
+                }
+            };
+            state_cell.deltas.push(delta);
+        }
+        let destination = self.planet_map[&expedition.destination];
+        dirty_planets.insert(destination);
+        self.expeditions.insert(expedition.id, TrackedExpedition { arrival_turn, destination, delta });
+    }
+
+    // The input is checked before anything is stored, so a rejected input leaves the state
+    // untouched.
+    pub fn update(&mut self, mut input: Input) -> Result<(), StateError> {
+        if input.planets.len() != self.planet_names.len() {
+            return Err(StateError::PlanetCountMismatch {
+                expected: self.planet_names.len(),
+                received: input.planets.len(),
+            });
+        }
+        let unknown_planet = input.planets.iter()
+            .map(|planet| &planet.name)
+            .chain(input.expeditions.iter().flat_map(|expedition| [&expedition.origin, &expedition.destination]))
+            .find(|name| !self.planet_map.contains_key(*name));
+        if let Some(name) = unknown_planet {
+            return Err(StateError::UnknownPlanet(name.clone()));
+        }
+
+        // what was expected for this turn, with the moves sent last turn
+        let planet_count = self.planet_names.len();
+        let predicted = (0..planet_count)
+            .map(|planet_id| self.predict_planet(0, planet_id))
+            .collect_vec();
+        // An expedition that is seen for the first time left last turn when it arrives a whole
+        // journey after it. Others were already underway, for example when an update failed.
+        let mut unplanned_departures = vec![0; planet_count];
+        for expedition in &input.expeditions {
+            let origin = self.planet_map[&expedition.origin];
+            let travel_turns = self.distance(origin, self.planet_map[&expedition.destination]).ceil() as i64;
+            if !self.expeditions.contains_key(&expedition.id) && expedition.turns_remaining + 1 == travel_turns {
+                unplanned_departures[origin] += expedition.ship_count;
+            }
+        }
+        for planned_move in &self.overlay.moves {
+            unplanned_departures[self.planet_map[&planned_move.origin]] -= planned_move.ship_count;
+        }
+
+        // moves planned last turn are now part of the observed expeditions
+        self.overlay = Overlay::default();
+        self.prune();
+
+        // planets with changed deltas or an unexpected observation need a new timeline
+        let mut dirty_planets = BitSet::new();
+        self.retire_expeditions(&input, &mut dirty_planets);
+        for expedition in &input.expeditions {
+            self.track_expedition(expedition, &mut dirty_planets);
         }
         input.planets.sort_by_key(|planet| self.planet_map[&planet.name]);
         self.current_state = input;
+        self.drift = (0..planet_count)
+            .filter_map(|planet_id| {
+                let planet = &self.current_state.planets[planet_id];
+                let (owner, ship_count) = predicted[planet_id];
+                let predicted = (owner, ship_count - unplanned_departures[planet_id]);
+                let observed = (planet.owner, planet.ship_count);
+                (predicted != observed).then(|| Drift {
+                    turn: self.turn,
+                    planet: planet.name.clone(),
+                    predicted,
+                    observed,
+                })
+            })
+            .collect();
+        for drift in &self.drift {
+            warn!("{drift}");
+        }
+
+        // A timeline is still valid when it predicted what is observed now, every later entry
+        // only depends on the previous one and the deltas. Otherwise it is rebuilt from the
+        // observation, which corrects the drift.
+        for planet_id in 0..planet_count {
+            let planet = &self.current_state.planets[planet_id];
+            let timeline = &self.timelines[planet_id];
+            if timeline.at(self.turn) != (planet.owner, planet.ship_count) {
+                dirty_planets.insert(planet_id);
+            }
+        }
+        self.recompute_timelines(dirty_planets.iter());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Drift, State};
+    use crate::{MAX_TURNS, structs::{Expedition, GameSituation, Move}, test_util::{expedition, input}};
+
+    #[test]
+    fn current_turn_is_the_observation() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], vec![expedition(0, "b", 1, 8, 2)])).unwrap();
+        assert_eq!(state.predict_planet(0, 1), (None, 5));
+        assert_eq!(state.predict_planet(2, 1), (Some(1), 3));
+
+        // the arrival is part of the observation and is not applied a second time
+        state.tick();
+        state.update(input(&[("a", Some(1), 12), ("b", None, 5)], vec![])).unwrap();
+        state.tick();
+        state.update(input(&[("a", Some(1), 13), ("b", Some(1), 3)], vec![])).unwrap();
+        assert_eq!(state.predict_planet(0, 1), (Some(1), 3));
+        assert_eq!(state.predict_planet(1, 1), (Some(1), 4));
+    }
+
+    #[test]
+    fn predictions_stop_changing_after_the_last_turn() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", Some(2), 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 20;
+        assert_eq!(state.predict_planet(20, 0), (Some(1), 30));
+        assert_eq!(state.predict_planet(21, 0), (Some(1), 30));
+        assert_eq!(state.predict_planet(1000, 1), (Some(2), 25));
+    }
+
+    #[test]
+    fn arrivals_after_the_last_turn_are_ignored() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 5;
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], vec![expedition(0, "b", 1, 20, 10)])).unwrap();
+        assert_eq!(state.predict_planet(4, 1), (None, 5));
+        assert_eq!(state.predict_planet(100, 1), (None, 5));
+    }
+
+    #[test]
+    fn expeditions_beyond_max_turns_do_not_panic() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 2000;
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], vec![expedition(0, "b", 2, 20, 700)])).unwrap();
+        assert_eq!(state.predict_planet(699, 1), (None, 5));
+        assert_eq!(state.predict_planet(700, 1), (Some(2), 15));
+        assert_eq!(state.predict_planet(710, 1), (Some(2), 25));
+    }
+
+    #[test]
+    fn last_turn_of_the_game() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 3;
+        for turn in 1..=3 {
+            state.tick();
+            state.update(input(&[("a", Some(1), 10 + turn), ("b", None, 5)], vec![])).unwrap();
+        }
+        assert_eq!(state.predict_planet(0, 0), (Some(1), 13));
+        assert_eq!(state.predict_planet(5, 0), (Some(1), 13));
+
+        let planned_move = Move { origin: "a".to_string(), destination: "b".to_string(), ship_count: 10 };
+        state.plan_move(&planned_move).unwrap();
+        assert_eq!(state.predict_planet(0, 0), (Some(1), 3));
+        assert_eq!(state.predict_planet(20, 1), (None, 5));
+
+        // the server may keep sending turns after the limit
+        for turn in 4..=5 {
+            state.tick();
+            state.update(input(&[("a", Some(1), 10 + turn), ("b", None, 5)], vec![])).unwrap();
+        }
+        state.plan_move(&planned_move).unwrap();
+        assert!(state.state.len() <= 1);
+        assert_eq!(state.predict_planet(0, 0), (Some(1), 5));
+    }
+
+    #[test]
+    fn drift_is_reported_and_corrected() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", Some(2), 5)], vec![]), 1, MAX_TURNS);
+        let planned_move = Move { origin: "a".to_string(), destination: "b".to_string(), ship_count: 4 };
+        state.plan_move(&planned_move).unwrap();
+        state.tick();
+        // our move and the one of the other player are expected, the 3 missing ships on b are not
+        let expeditions = vec![expedition(0, "b", 1, 4, 9), Expedition { origin: "b".to_string(), ..expedition(1, "a", 2, 2, 9) }];
+        state.update(input(&[("a", Some(1), 7), ("b", Some(2), 1)], expeditions)).unwrap();
+        assert_eq!(state.drift, vec![Drift {
+            turn: 1,
+            planet: "b".to_string(),
+            predicted: (Some(2), 4),
+            observed: (Some(2), 1),
+        }]);
+        assert_eq!(state.predict_planet(9, 1), (Some(2), 6));
+    }
+
+    #[test]
+    fn vanished_expeditions_are_removed() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], vec![expedition(u64::MAX, "b", 2, 8, 3)])).unwrap();
+        assert_eq!(state.predict_planet(3, 1), (Some(2), 3));
+
+        state.tick();
+        state.update(input(&[("a", Some(1), 12), ("b", None, 5)], vec![])).unwrap();
+        assert_eq!(state.predict_planet(2, 1), (None, 5));
+        assert!(state.expeditions.is_empty());
+    }
+
+    #[test]
+    fn ships_underway_keep_a_player_in_the_game() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        assert_eq!(state.check_gameover(), GameSituation::Won);
+
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], vec![expedition(0, "b", 2, 20, 3)])).unwrap();
+        assert_eq!(state.check_gameover(), GameSituation::Ongoing);
+        assert!(!state.is_decided());
+
+        state.tick();
+        state.update(input(&[("a", None, 0), ("b", None, 5)], vec![expedition(0, "b", 2, 20, 2)])).unwrap();
+        assert_eq!(state.check_gameover(), GameSituation::Lost);
+    }
+
+    #[test]
+    fn turn_limit_ends_the_game() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", Some(2), 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 1;
+        assert_eq!(state.check_gameover(), GameSituation::Ongoing);
+
+        state.tick();
+        state.update(input(&[("a", Some(1), 11), ("b", Some(2), 5)], vec![expedition(0, "a", 2, 6, 4)])).unwrap();
+        assert_eq!(state.check_gameover(), GameSituation::Draw);
+    }
+
+    #[test]
+    fn arrivals_far_beyond_the_last_turn_are_not_stored() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        let expeditions = vec![expedition(0, "b", 2, 20, 1_000_000_000_000), expedition(1, "b", 2, 20, i64::MAX)];
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], expeditions.clone())).unwrap();
+        assert!(state.state.len() <= state.max_turns as usize + 1);
+        assert_eq!(state.predict_planet(10_000, 1), (None, 5));
+        assert!(state.hostile_arrivals().is_empty());
+
+        // still tracked, so they are not new in the next turn
+        state.tick();
+        state.update(input(&[("a", Some(1), 12), ("b", None, 5)], expeditions)).unwrap();
+        assert!(state.drift.is_empty());
+        state.tick();
+        state.update(input(&[("a", Some(1), 13), ("b", None, 5)], vec![])).unwrap();
+        assert_eq!(state.predict_planet(0, 1), (None, 5));
+    }
+
+    #[test]
+    fn expeditions_in_flight_at_the_start_are_predicted() {
+        let expeditions = vec![expedition(0, "b", 2, 8, 3)];
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], expeditions.clone()), 1, MAX_TURNS);
+        assert_eq!(state.predict_planet(3, 1), (Some(2), 3));
+        assert_eq!(state.hostile_arrivals().into_iter().collect::<Vec<_>>(), vec![((3, 1), 8)]);
+
+        // not new in the next turn, so it is neither stored twice nor taken for a departure
+        state.tick();
+        let mut next = expeditions;
+        next[0].turns_remaining = 2;
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], next)).unwrap();
+        assert!(state.drift.is_empty());
+        assert_eq!(state.predict_planet(2, 1), (Some(2), 3));
+    }
+
+    #[test]
+    fn expeditions_seen_mid_flight_are_no_departures() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.tick();
+        // a full journey from a to b takes 10 turns
+        let expeditions = vec![expedition(0, "b", 2, 3, 4), expedition(1, "b", 2, 2, 9)];
+        state.update(input(&[("a", Some(1), 9), ("b", None, 5)], expeditions)).unwrap();
+        assert!(state.drift.is_empty(), "{:?}", state.drift);
+    }
+
+    #[test]
+    fn hostile_arrivals_are_summed() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        state.max_turns = 10;
+        state.tick();
+        let expeditions = vec![
+            expedition(0, "a", 2, 4, 3),
+            expedition(1, "a", 3, 6, 3),
+            expedition(2, "b", 2, 7, 2),
+            expedition(3, "b", 1, 8, 2),
+            expedition(4, "a", 2, 9, 20),
+        ];
+        state.update(input(&[("a", Some(1), 11), ("b", None, 5)], expeditions)).unwrap();
+        let arrivals = state.hostile_arrivals().into_iter().collect::<Vec<_>>();
+        assert_eq!(arrivals, vec![((2, 1), 7), ((3, 0), 10)]);
+    }
+
+    #[test]
+    fn passed_turns_are_pruned() {
+        let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 5)], vec![]), 1, MAX_TURNS);
+        for turn in 1..=600 {
+            state.tick();
+            let expeditions = vec![expedition(turn as u64, "b", 2, 1, 3)];
+            state.update(input(&[("a", Some(1), 10), ("b", None, 5)], expeditions)).unwrap();
+        }
+        assert_eq!(state.first_turn, state.turn);
+        assert!(state.state.len() <= 4);
     }
 }
@@ -0,0 +1,76 @@
+use serde_json::{Map, Value};
+
+use crate::{
+    state::State,
+    structs::Move,
+    config::ConfigError,
+    deadline::Deadline,
+    metrics::TurnMetrics,
+    graph_export::GraphExportOptions,
+    neighbourhood::{NeighbourhoodAlrorithm, NeighbourhoodConfig},
+    simple_algoritm::SimpleAlrorithm,
+    direct_flow::Flow1Algorithm,
+    ants::AntColonyOptimizationAlgorithm,
+};
+
+pub const DEFAULT_STRATEGY: &str = "neighbourhood";
+pub const STRATEGY_NAMES: [&str; 4] = ["neighbourhood", "simple", "flow", "ants"];
+
+pub trait Strategy {
+    // name used to select the strategy at startup
+    fn name(&self) -> &'static str;
+
+    // Plans the moves of this turn. When the deadline expires the strategy should stop
+    // searching and return the best moves it has found so far.
+    fn calculate(&mut self, state: &mut State, deadline: &Deadline) -> Vec<Move>;
+
+    // called once, after the state has been built from the first turn
+    fn setup(&mut self, _state: &State) {}
+
+    // called once, after the last turn has been played
+    fn teardown(&mut self, _state: &State) {}
+
+    // adds measurements of the last `calculate` that only the strategy knows about
+    fn report_metrics(&self, _metrics: &mut TurnMetrics) {}
+
+    // strategies that build graphs export them for the turns the options ask for
+    fn enable_graph_export(&mut self, _options: GraphExportOptions) {}
+}
+
+// Builds the strategy with the given name, deserializing `params` into its config struct.
+// Parameters that are not set keep their default value.
+pub fn build_strategy(name: &str, params: Map<String, Value>) -> Result<Box<dyn Strategy>, ConfigError> {
+    let params = Value::Object(params);
+    let strategy: Box<dyn Strategy> = match name {
+        "neighbourhood" => Box::new(NeighbourhoodAlrorithm::new(serde_json::from_value(params)?)),
+        "simple" => Box::new(SimpleAlrorithm::new(serde_json::from_value(params)?)),
+        "flow" => Box::new(Flow1Algorithm::new(serde_json::from_value(params)?)),
+        "ants" => Box::new(AntColonyOptimizationAlgorithm::new(serde_json::from_value(params)?)),
+        _ => return Err(ConfigError::UnknownStrategy(name.to_string())),
+    };
+    Ok(strategy)
+}
+
+// cheap plan for when there is no time left to run the chosen strategy
+pub fn fallback_strategy() -> Box<dyn Strategy> {
+    Box::new(NeighbourhoodAlrorithm::new(NeighbourhoodConfig::cheap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{STRATEGY_NAMES, build_strategy};
+    use crate::{MAX_TURNS, deadline::Deadline, state::State, test_util::input};
+
+    #[test]
+    fn strategies_without_look_ahead_do_not_panic() {
+        for name in STRATEGY_NAMES {
+            let mut state = State::new(input(&[("a", Some(1), 10), ("b", None, 2), ("c", Some(2), 5)], vec![]), 1, MAX_TURNS);
+            let params = json!({ "look_ahead": 0 }).as_object().unwrap().clone();
+            let mut strategy = build_strategy(name, params).unwrap();
+            strategy.setup(&state);
+            strategy.calculate(&mut state, &Deadline::unlimited());
+        }
+    }
+}
@@ -2,24 +2,28 @@ use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GameSituation {
     Won,
     Lost,
+    Draw,
     Ongoing,
 }
 
 pub type PlanetName = String;
 pub type ExpeditionId = u64;
 pub type PlayerId = u8;
+// the server sends the game from the perspective of the bot, which always is player 1
+pub const DEFAULT_PLAYER_ID: PlayerId = 1;
 pub type PlanetId = usize;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Input {
     pub planets: Vec<Planet>,
     pub expeditions: Vec<Expedition>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Planet {
     pub ship_count: i64,
     pub x: f32,
@@ -51,7 +55,7 @@ impl From<&Planet> for PlanetLocation {
 }
 
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Expedition {
     pub id: ExpeditionId,
     pub ship_count: i64,
@@ -66,7 +70,7 @@ pub struct Output {
     pub  moves: Vec<Move>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Move {
     pub origin: PlanetName,
     pub destination: PlanetName,
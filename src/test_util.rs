@@ -0,0 +1,30 @@
+// Builders for the inputs of unit tests
+
+use crate::structs::{Expedition, Input, Planet};
+
+// planets on a line, 10 apart, in the given order
+pub fn input(planets: &[(&str, Option<u8>, i64)], expeditions: Vec<Expedition>) -> Input {
+    let planets = planets.iter()
+        .enumerate()
+        .map(|(index, &(name, owner, ship_count))| Planet {
+            ship_count,
+            x: 10.0 * index as f32,
+            y: 0.0,
+            owner,
+            name: name.to_string(),
+        })
+        .collect();
+    Input { planets, expeditions }
+}
+
+// an expedition from planet `a`
+pub fn expedition(id: u64, destination: &str, owner: u8, ship_count: i64, turns_remaining: i64) -> Expedition {
+    Expedition {
+        id,
+        ship_count,
+        origin: "a".to_string(),
+        destination: destination.to_string(),
+        owner,
+        turns_remaining,
+    }
+}
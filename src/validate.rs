@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{state::State, structs::{Move, PlanetName}};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropReason {
+    UnknownPlanet(PlanetName),
+    NotOwned,
+    SameOriginAndDestination,
+    NoShips,
+}
+
+#[derive(Debug, Clone)]
+pub enum Correction {
+    Dropped { dropped: Move, reason: DropReason },
+    // several moves between the same planets were sent as a single one
+    Merged { origin: PlanetName, destination: PlanetName, count: usize },
+    // the moves of an origin asked for more ships than it has, later moves were lowered
+    Clamped { origin: PlanetName, requested: i64, available: i64 },
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Correction::Dropped { dropped, reason } => write!(
+                f, "dropped {} ships from {} to {}: {reason:?}",
+                dropped.ship_count, dropped.origin, dropped.destination
+            ),
+            Correction::Merged { origin, destination, count } => {
+                write!(f, "merged {count} moves from {origin} to {destination}")
+            },
+            Correction::Clamped { origin, requested, available } => {
+                write!(f, "{origin} has {available} ships, {requested} requested")
+            },
+        }
+    }
+}
+
+// Checks the moves of a strategy against the current state so the server never rejects the
+// turn. Earlier moves take precedence over later ones when an origin runs out of ships.
+pub fn validate_moves(state: &State, moves: Vec<Move>) -> (Vec<Move>, Vec<Correction>) {
+    let mut corrections = Vec::new();
+
+    let mut merged: Vec<Move> = Vec::new();
+    let mut merge_counts: BTreeMap<(PlanetName, PlanetName), usize> = BTreeMap::new();
+    for planned_move in moves {
+        if let Some(reason) = check_move(state, &planned_move) {
+            corrections.push(Correction::Dropped { dropped: planned_move, reason });
+            continue;
+        }
+        let key = (planned_move.origin.clone(), planned_move.destination.clone());
+        let count = merge_counts.entry(key).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            merged.push(planned_move);
+        } else {
+            let existing = merged.iter_mut()
+                .find(|existing| existing.origin == planned_move.origin && existing.destination == planned_move.destination)
+                .expect("move was counted before");
+            existing.ship_count += planned_move.ship_count;
+        }
+    }
+    for ((origin, destination), count) in merge_counts {
+        if count > 1 {
+            corrections.push(Correction::Merged { origin, destination, count });
+        }
+    }
+
+    let mut available: BTreeMap<PlanetName, i64> = BTreeMap::new();
+    let mut requested: BTreeMap<PlanetName, i64> = BTreeMap::new();
+    let mut valid = Vec::new();
+    for mut planned_move in merged {
+        *requested.entry(planned_move.origin.clone()).or_insert(0) += planned_move.ship_count;
+        let available = available.entry(planned_move.origin.clone()).or_insert_with(|| {
+            let planet_id = state.planet_map[&planned_move.origin];
+            state.current_state.planets[planet_id].ship_count
+        });
+        planned_move.ship_count = planned_move.ship_count.min(*available);
+        *available -= planned_move.ship_count;
+        if planned_move.ship_count > 0 {
+            valid.push(planned_move);
+        }
+    }
+    for (origin, requested) in requested {
+        let planet_id = state.planet_map[&origin];
+        let available = state.current_state.planets[planet_id].ship_count;
+        if requested > available {
+            corrections.push(Correction::Clamped { origin, requested, available });
+        }
+    }
+
+    (valid, corrections)
+}
+
+fn check_move(state: &State, planned_move: &Move) -> Option<DropReason> {
+    for name in [&planned_move.origin, &planned_move.destination] {
+        if !state.planet_map.contains_key(name) {
+            return Some(DropReason::UnknownPlanet(name.clone()));
+        }
+    }
+    if planned_move.origin == planned_move.destination {
+        return Some(DropReason::SameOriginAndDestination);
+    }
+    if planned_move.ship_count <= 0 {
+        return Some(DropReason::NoShips);
+    }
+    let origin = &state.current_state.planets[state.planet_map[&planned_move.origin]];
+    if origin.owner != state.own_id() {
+        return Some(DropReason::NotOwned);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_moves, Correction, DropReason};
+    use crate::{MAX_TURNS, state::State, structs::Move, test_util::input};
+
+    fn send(origin: &str, destination: &str, ship_count: i64) -> Move {
+        Move { origin: origin.to_string(), destination: destination.to_string(), ship_count }
+    }
+
+    fn state() -> State {
+        State::new(input(&[("a", Some(1), 10), ("b", None, 5), ("c", Some(2), 4), ("d", Some(1), 3)], vec![]), 1, MAX_TURNS)
+    }
+
+    #[test]
+    fn moves_between_the_same_planets_are_merged() {
+        let (valid, corrections) = validate_moves(&state(), vec![send("a", "b", 2), send("a", "c", 1), send("a", "b", 3)]);
+        assert_eq!(valid, vec![send("a", "b", 5), send("a", "c", 1)]);
+        assert!(matches!(
+            corrections.as_slice(),
+            [Correction::Merged { origin, destination, count: 2 }] if origin == "a" && destination == "b"
+        ));
+    }
+
+    #[test]
+    fn later_moves_are_clamped_to_the_ships_of_the_origin() {
+        let moves = vec![send("a", "b", 7), send("a", "c", 6), send("a", "d", 1), send("d", "b", 3)];
+        let (valid, corrections) = validate_moves(&state(), moves);
+        assert_eq!(valid, vec![send("a", "b", 7), send("a", "c", 3), send("d", "b", 3)]);
+        assert!(matches!(
+            corrections.as_slice(),
+            [Correction::Clamped { origin, requested: 14, available: 10 }] if origin == "a"
+        ));
+    }
+
+    #[test]
+    fn invalid_moves_are_dropped() {
+        let moves = vec![
+            send("a", "b", 0),
+            send("a", "b", -2),
+            send("a", "a", 3),
+            send("c", "b", 1),
+            send("a", "x", 1),
+            send("a", "b", 1),
+        ];
+        let (valid, corrections) = validate_moves(&state(), moves);
+        assert_eq!(valid, vec![send("a", "b", 1)]);
+        let reasons = corrections.iter()
+            .map(|correction| match correction {
+                Correction::Dropped { reason, .. } => reason.clone(),
+                other => panic!("unexpected correction {other}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, vec![
+            DropReason::NoShips,
+            DropReason::NoShips,
+            DropReason::SameOriginAndDestination,
+            DropReason::NotOwned,
+            DropReason::UnknownPlanet("x".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn valid_moves_are_not_corrected() {
+        let moves = vec![send("a", "b", 10), send("d", "c", 3)];
+        let (valid, corrections) = validate_moves(&state(), moves.clone());
+        assert_eq!(valid, moves);
+        assert!(corrections.is_empty());
+    }
+}